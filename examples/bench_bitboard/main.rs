@@ -0,0 +1,126 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use std::time::Instant;
+
+use rstris::bitboard::BitBoard;
+use rstris::block::*;
+use rstris::board::Board;
+use rstris::figure::*;
+use rstris::occupancy_grid::OccupancyGrid;
+use rstris::playfield::Playfield;
+use rstris::position::Position;
+
+static PF_WIDTH: u32 = 10;
+static PF_HEIGHT: u32 = 22;
+static ROUNDS: u32 = 2000;
+
+macro_rules! bl {
+    ($x:expr) => {
+        match $x {
+            0 => Block::Clear,
+            _ => Block::Set($x),
+        }
+    };
+}
+
+fn init_figures() -> Vec<Figure> {
+    vec![
+        Figure::new_from_face(
+            "1",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(1), bl!(1), bl!(1)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        ),
+        Figure::new_from_face("5", &[&[bl!(0), bl!(5), bl!(5)], &[bl!(5), bl!(5), bl!(0)]]),
+        Figure::new_from_face(
+            "7",
+            &[
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+            ],
+        ),
+    ]
+}
+
+fn random_pf(rng: &mut StdRng) -> Playfield {
+    let mut pf = Playfield::new("bench", PF_WIDTH, PF_HEIGHT);
+    for y in (PF_HEIGHT / 2) as i32..PF_HEIGHT as i32 {
+        let full = rng.gen_range(0, 4) == 0;
+        for x in 0..PF_WIDTH as i32 {
+            if full || rng.gen_range(0, 3) != 0 {
+                pf.set_block((x, y).into(), Block::Set(1));
+            }
+        }
+    }
+    pf
+}
+
+fn bench<F>(name: &str, mut f: F)
+where
+    F: FnMut() -> u64,
+{
+    let start = Instant::now();
+    let mut result = 0;
+    for _ in 0..ROUNDS {
+        result += f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<28} {:>10.3} us/round (result {})",
+        name,
+        elapsed.as_secs_f64() * 1_000_000.0 / f64::from(ROUNDS),
+        result
+    );
+}
+
+fn bench_board<B: Board>(
+    name: &str,
+    board: &B,
+    figures: &[Figure],
+    positions: &[(usize, Position)],
+) {
+    bench(&format!("collision ({})", name), || {
+        positions
+            .iter()
+            .filter(|(fig, pos)| figures[*fig].test_collision(board, *pos))
+            .count() as u64
+    });
+    bench(&format!("drop distance ({})", name), || {
+        positions
+            .iter()
+            .filter(|(_fig, pos)| pos.y() == 0)
+            .map(|(fig, pos)| u64::from(figures[*fig].drop_distance(board, *pos)))
+            .sum()
+    });
+    bench(&format!("full lines ({})", name), || {
+        u64::from(board.count_locked_lines())
+    });
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let figures = init_figures();
+    let pf = random_pf(&mut rng);
+    let grid = OccupancyGrid::from_playfield(&pf);
+    let bb = BitBoard::from_playfield(&pf);
+
+    let mut positions = Vec::new();
+    for (fig_index, fig) in figures.iter().enumerate() {
+        for dir in 0..i32::from(fig.num_faces()) {
+            for x in -2..PF_WIDTH as i32 {
+                for y in 0..PF_HEIGHT as i32 {
+                    positions.push((fig_index, (x, y, dir).into()));
+                }
+            }
+        }
+    }
+
+    bench_board("playfield", &pf, &figures, &positions);
+    bench_board("occupancy grid", &grid, &figures, &positions);
+    bench_board("bitboard", &bb, &figures, &positions);
+}
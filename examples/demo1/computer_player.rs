@@ -1,120 +1,103 @@
 use log::*;
 
+use rstris::ai::*;
+use rstris::bitboard::BitBoard;
 use rstris::figure::Figure;
 use rstris::find_path::*;
-use rstris::find_placement::*;
+use rstris::input_script::*;
 use rstris::movement::Movement;
-use rstris::playfield::Playfield;
 use rstris::position::Position;
 
 use crate::game::Game;
 
-pub trait ComputerType {
-    fn init_eval(&mut self, pf: &Playfield, avail_placings: usize);
-    fn eval_placing(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> f32;
-}
-
-struct EvalPosition {
-    pos: Position,
-    eval: f32,
-}
+// Length of a frame of the input script in ticks
+static FRAME_TIME: u64 = 1_000_000;
 
-pub struct ComputerPlayer<T>
+pub struct ComputerPlayer<E>
 where
-    T: ComputerType,
+    E: Evaluator<BitBoard>,
 {
-    com_type: T,
-    moves_per_down_step: f32,
+    beam: BeamSearch<E>,
     last_figure: Option<(Figure, Position)>,
 
     // Some cache variables
-    avail_placings: Vec<Position>,
-    find_path: FindPath,
-    eval_placing: Vec<EvalPosition>,
-    path: Vec<Movement>,
-    moves_per_level: Vec<(i32, Movement)>,
+    script: Vec<InputEvent>,
+    movements: Vec<Movement>,
+
+    // Input of the current figure, the ticks when it appeared and the
+    // next frame to play
+    driver: Option<InputDriver>,
+    start_ticks: u64,
+    next_frame: u64,
 }
 
-impl<T> ComputerPlayer<T>
+impl<E> ComputerPlayer<E>
 where
-    T: ComputerType,
+    E: Evaluator<BitBoard>,
 {
-    pub fn new(moves_per_down_step: f32, com_type: T) -> Self {
+    pub fn new(moves_per_down_step: u32, evaluator: E) -> Self {
         ComputerPlayer {
-            moves_per_down_step,
-            com_type,
-            moves_per_level: Vec::new(),
+            beam: BeamSearch::new(
+                evaluator,
+                SearchGravity::moves_per_row(moves_per_down_step),
+                // The game has no hold, plan with the next figure
+                BeamConfig {
+                    use_hold: false,
+                    ..BeamConfig::default()
+                },
+            ),
             last_figure: None,
-            eval_placing: Vec::new(),
-            path: Vec::new(),
-            find_path: FindPath::default(),
-            avail_placings: Vec::new(),
+            script: Vec::new(),
+            movements: Vec::new(),
+            driver: None,
+            start_ticks: 0,
+            next_frame: 0,
         }
     }
 
-    fn figure_move_event(&mut self, game: &mut Game, ticks: u64, _fig: &Figure, pos: Position) {
-        let last_y = match self.last_figure {
-            Some((_, ref last_fig_pos)) => last_fig_pos.y(),
-            None => -1,
-        };
-        let y = pos.y();
-        if y > last_y {
-            let mut move_time = 0;
-            while !self.moves_per_level.is_empty() && y == self.moves_per_level[0].0 {
-                let movement = self.moves_per_level.remove(0);
-                game.add_move(movement.1, ticks + move_time);
-                move_time += (game.down_step_time() as f32 / self.moves_per_down_step) as u64;
-            }
+    fn input_timing(&self, game: &Game) -> InputTiming {
+        InputTiming {
+            das: 3,
+            arr: 1,
+            gravity: (game.down_step_time() / FRAME_TIME) as u32,
+            soft_drop: Some(1),
         }
     }
 
-    fn new_figure_event(&mut self, _ticks: u64, pf: &Playfield, fig: &Figure, pos: Position) {
-        // Find all possible positions where figure can be placed
-        self.avail_placings.clear();
-        find_placement(&mut self.avail_placings, &pf, fig);
-
-        // Evaluate all placings to find the best one
-        self.com_type.init_eval(&pf, self.avail_placings.len());
-        self.eval_placing.clear();
-        for avail_pos in &self.avail_placings {
-            let eval = self.com_type.eval_placing(&pf, &fig, *avail_pos);
-            let eval_pos = EvalPosition {
-                pos: *avail_pos,
-                eval,
-            };
-            self.eval_placing.push(eval_pos);
-        }
-        self.eval_placing
-            .sort_by(|a, b| b.eval.partial_cmp(&a.eval).unwrap());
-
-        // Find a path to first (and best) available placing
-        self.path.clear();
-        for eval_pos in &self.eval_placing {
-            self.find_path.search(
-                &mut self.path,
-                &pf,
-                fig,
-                pos,
-                eval_pos.pos,
-                self.moves_per_down_step,
-            );
-            if !self.path.is_empty() {
-                break;
+    // Make the moves of all frames of the input script up until ticks
+    fn play_script(&mut self, game: &mut Game, ticks: u64) {
+        if let Some(ref mut driver) = self.driver {
+            let frame = (ticks - self.start_ticks) / FRAME_TIME;
+            while self.next_frame <= frame {
+                self.movements.clear();
+                driver.update(self.next_frame, &mut self.movements);
+                for movement in &self.movements {
+                    game.add_move(*movement, self.start_ticks + self.next_frame * FRAME_TIME);
+                }
+                self.next_frame += 1;
             }
         }
+    }
 
-        self.moves_per_level.clear();
-        if !self.path.is_empty() {
-            self.path.reverse();
-
-            // Convert the path from being in exact Movements to
-            // describe the sideways/rotational movements per height level
-            path_to_moves_per_level(&mut self.moves_per_level, &self.path);
-            info!(
-                "Found path for figure {} ({} available placements)",
-                fig.name(),
-                self.avail_placings.len()
-            );
+    fn new_figure_event(&mut self, ticks: u64, game: &Game, fig: &Figure, pos: Position) {
+        let timing = self.input_timing(game);
+        self.driver = None;
+        let next = [game.next_figure().clone()];
+        if let Some(choice) = self.beam.search(game.playfield(), fig, None, &next, pos) {
+            // Convert the path into key presses timed for the game
+            match path_to_script(&mut self.script, &choice.path, &timing) {
+                Ok(()) => {
+                    info!(
+                        "Found path for figure {} ({} placements evaluated)",
+                        fig.name(),
+                        self.beam.expanded_placements()
+                    );
+                    self.driver = Some(InputDriver::new(self.script.clone(), timing));
+                    self.start_ticks = ticks;
+                    self.next_frame = 0;
+                }
+                Err(err) => warn!("Unable to play path for figure {}: {}", fig.name(), err),
+            }
         }
     }
 
@@ -122,28 +105,16 @@ where
         if self.last_figure != *game.current_figure() {
             // Figure has changed since last call
             let current_figure = game.current_figure().clone();
-            if let Some((ref fig, pos)) = current_figure {
-                if self.last_figure == None {
-                    // Test if new figure
-                    self.new_figure_event(ticks, game.playfield(), fig, pos);
-                    self.figure_move_event(game, ticks, fig, pos);
-                } else {
-                    self.figure_move_event(game, ticks, fig, pos);
+            match current_figure {
+                Some((ref fig, pos)) if self.last_figure.is_none() => {
+                    // New figure
+                    self.new_figure_event(ticks, game, fig, pos);
                 }
+                Some(_) => {}
+                None => self.driver = None,
             }
             self.last_figure = current_figure;
         }
-    }
-}
-
-fn path_to_moves_per_level(moves: &mut Vec<(i32, Movement)>, path: &[Movement]) {
-    moves.clear();
-    let mut level: i32 = 0;
-    for movement in path {
-        if *movement == Movement::MoveDown {
-            level += 1;
-        } else {
-            moves.push((level, *movement));
-        }
+        self.play_script(game, ticks);
     }
 }
@@ -116,11 +116,11 @@ impl DrawContext {
         }
     }
     pub fn clear(&mut self, canvas: &mut Canvas<Window>, color: Color) {
-        let _ = canvas.set_draw_color(color);
-        let _ = canvas.clear();
+        canvas.set_draw_color(color);
+        canvas.clear();
     }
 
     pub fn present(&mut self, canvas: &mut Canvas<Window>) {
-        let _ = canvas.present();
+        canvas.present();
     }
 }
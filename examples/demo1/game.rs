@@ -1,5 +1,4 @@
 use log::*;
-use rand;
 
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
@@ -186,11 +185,7 @@ impl Game {
             self.move_queue.clear();
 
             // Throw away full lines
-            let mut full_lines = self.pf.locked_lines();
-            full_lines.sort();
-            for line in &full_lines {
-                self.pf.throw_line(*line);
-            }
+            self.pf.clear_lines(Gravity::Naive);
 
             // Place the next figure
             let new_figure = self.next_figure.clone();
@@ -1,22 +1,15 @@
-use fern;
-use log;
-
 use log::*;
 
-use sdl2;
-use time;
-
 mod computer_player;
 mod draw;
 mod game;
-mod jitter_computer;
 
 use crate::game::*;
 
 use crate::computer_player::*;
 use crate::draw::*;
-use crate::jitter_computer::*;
 
+use rstris::ai::JitterEvaluator;
 use rstris::block::*;
 use rstris::figure::*;
 use rstris::playfield::Playfield;
@@ -43,7 +36,7 @@ macro_rules! bl {
 //
 // Build list of figures
 //
-#[allow(clippy::cognitive_complexity)]
+#[allow(clippy::cognitive_complexity, clippy::vec_init_then_push)]
 fn init_figures() -> Vec<Figure> {
     let mut figure_list: Vec<Figure> = Vec::new();
     figure_list.push(Figure::new_from_face(
@@ -98,15 +91,10 @@ fn get_max_figure_dimensions(figure_list: &[Figure]) -> (u32, u32) {
     let mut max_width = 0;
     let mut max_height = 0;
     for fig in figure_list {
-        for face in fig.iter_faces() {
-            max_width = std::cmp::max(
-                max_width,
-                u32::from(face.iter().map(|(x, _y, _id)| *x).max().unwrap() + 1),
-            );
-            max_height = std::cmp::max(
-                max_height,
-                u32::from(face.iter().map(|(_x, y, _id)| *y).max().unwrap() + 1),
-            );
+        for dir in 0..i32::from(fig.num_faces()) {
+            let geometry = fig.geometry(dir);
+            max_width = std::cmp::max(max_width, u32::from(geometry.max_x() + 1));
+            max_height = std::cmp::max(max_height, u32::from(geometry.max_y() + 1));
         }
     }
     (max_width, max_height)
@@ -164,7 +152,7 @@ fn main() {
     let mut draw = DrawContext::new(BLOCK_SIZE, BLOCK_SPACING, frame_color, fill_color);
     let mut events = sdl_context.event_pump().unwrap();
 
-    let mut com1 = ComputerPlayer::new(1.0, JitterComputer::new());
+    let mut com1 = ComputerPlayer::new(1, JitterEvaluator::new());
 
     let mut game = Game::new(
         Playfield::new("Playfield 1", PF_WIDTH, PF_HEIGHT),
@@ -206,9 +194,7 @@ fn main() {
                 Event::KeyUp {
                     keycode: Some(key), ..
                 } => {
-                    if pressed_keys.contains_key(&key) {
-                        pressed_keys.remove(&key);
-                    }
+                    pressed_keys.remove(&key);
                 }
 
                 _ => {}
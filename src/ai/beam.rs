@@ -0,0 +1,442 @@
+use std::iter;
+use std::time::{Duration, Instant};
+
+use crate::ai::{Evaluator, TranspositionTable};
+use crate::bitboard::BitBoard;
+use crate::figure::Figure;
+use crate::find_path::{FindPath, Reachable, SearchGravity};
+use crate::movement::Movement;
+use crate::playfield::Playfield;
+use crate::position::Position;
+use crate::zobrist;
+
+//
+// Depth is the number of figures placed along each line of play, counting
+// the current figure. Only the beam_width best boards are kept after each
+// figure. When max_time runs out the search returns the best line found
+// in the figures searched so far, the first figure is always searched.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeamConfig {
+    pub depth: usize,
+    pub beam_width: usize,
+    pub max_time: Option<Duration>,
+    pub use_hold: bool,
+}
+
+impl Default for BeamConfig {
+    fn default() -> Self {
+        BeamConfig {
+            depth: 2,
+            beam_width: 16,
+            max_time: None,
+            use_hold: true,
+        }
+    }
+}
+
+//
+// First move of the best line of play found by the beam search
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeamChoice {
+    // Hold the current figure and play the figure in hold, or the next
+    // figure if hold is empty
+    pub hold: bool,
+    pub pos: Position,
+    pub path: Vec<Movement>,
+    // Sum of the evaluations along the best line
+    pub eval: f32,
+    // Number of figures placed along the best line
+    pub depth: usize,
+}
+
+struct BeamNode {
+    board: BitBoard,
+    // Index of the figure in hold and of the figure to play next
+    hold: Option<usize>,
+    next: usize,
+    eval: f32,
+    // Index into the first moves
+    first: usize,
+}
+
+// Placement not yet turned into a node, boards are only made for the
+// placements kept in the beam
+struct Candidate {
+    parent: usize,
+    fig: usize,
+    pos: Position,
+    hold: Option<usize>,
+    next: usize,
+    eval: f32,
+    first: usize,
+}
+
+struct FirstMove {
+    hold: bool,
+    pos: Position,
+    path: Vec<Movement>,
+}
+
+//
+// Plans ahead over the next figures and hold by keeping the best boards
+// after each placed figure. Every placement is scored by the evaluator on
+// the board it's placed on, and a line of play is scored by the sum of the
+// scores of its placements. Full lines are cleared between the figures.
+// Lines of play reaching the same board with the same figures left are
+// only kept once, with the best score.
+//
+pub struct BeamSearch<E>
+where
+    E: Evaluator<BitBoard>,
+{
+    evaluator: E,
+    gravity: SearchGravity,
+    config: BeamConfig,
+    expanded: usize,
+
+    // Some cache variables
+    find_path: FindPath,
+    reachable: Vec<Reachable>,
+    seen: TranspositionTable<()>,
+}
+
+impl<E> BeamSearch<E>
+where
+    E: Evaluator<BitBoard>,
+{
+    pub fn new(evaluator: E, gravity: SearchGravity, config: BeamConfig) -> Self {
+        BeamSearch {
+            evaluator,
+            gravity,
+            config,
+            expanded: 0,
+            find_path: FindPath::new(),
+            reachable: Vec::new(),
+            seen: TranspositionTable::new(4096),
+        }
+    }
+
+    pub fn config(&self) -> &BeamConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: BeamConfig) {
+        self.config = config;
+    }
+
+    pub fn evaluator(&self) -> &E {
+        &self.evaluator
+    }
+
+    pub fn evaluator_mut(&mut self) -> &mut E {
+        &mut self.evaluator
+    }
+
+    pub fn find_path_mut(&mut self) -> &mut FindPath {
+        &mut self.find_path
+    }
+
+    //
+    // Number of placements evaluated in the last search
+    //
+    pub fn expanded_placements(&self) -> usize {
+        self.expanded
+    }
+
+    //
+    // Search for the best line of play with the figures in the order they
+    // will be played, all starting at start_pos. None if the current figure
+    // (or with hold, the figure it can be swapped for) can't be placed.
+    // Placements evaluated to NaN are skipped.
+    //
+    pub fn search(
+        &mut self,
+        pf: &Playfield,
+        current: &Figure,
+        hold: Option<&Figure>,
+        next: &[Figure],
+        start_pos: Position,
+    ) -> Option<BeamChoice> {
+        let deadline = self.config.max_time.map(|time| Instant::now() + time);
+        let queue_len = 1 + next.len();
+        // The figure in hold is kept after the queue
+        let figures: Vec<&Figure> = iter::once(current).chain(next.iter()).chain(hold).collect();
+        let start_hold = hold.map(|_| queue_len);
+
+        self.expanded = 0;
+        let mut first_moves = Vec::new();
+        let mut best = None;
+        let mut beam = vec![BeamNode {
+            board: BitBoard::from_playfield(pf),
+            hold: start_hold,
+            next: 0,
+            eval: 0.0,
+            first: 0,
+        }];
+        let mut candidates = Vec::new();
+        for depth in 0..self.config.depth {
+            if depth > 0 && deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            candidates.clear();
+            for (parent, node) in beam.iter().enumerate() {
+                if node.next >= queue_len {
+                    continue;
+                }
+                // Figure to play with the hold and next figure after it
+                let mut options = vec![(node.next, node.hold, node.next + 1, false)];
+                if self.config.use_hold {
+                    match node.hold {
+                        Some(held) if figures[held].name() != figures[node.next].name() => {
+                            options.push((held, Some(node.next), node.next + 1, true));
+                        }
+                        None if node.next + 1 < queue_len => {
+                            options.push((node.next + 1, Some(node.next), node.next + 2, true));
+                        }
+                        _ => {}
+                    }
+                }
+
+                for (fig, hold, next, held) in options {
+                    let figure = figures[fig];
+                    self.find_path.search_all(
+                        &mut self.reachable,
+                        &node.board,
+                        figure,
+                        start_pos,
+                        self.gravity,
+                    );
+                    self.evaluator.init_eval(&node.board, self.reachable.len());
+                    for reachable in &self.reachable {
+                        let eval = self
+                            .evaluator
+                            .eval_placing(&node.board, figure, reachable.pos);
+                        if eval.is_nan() {
+                            continue;
+                        }
+                        let first = if depth == 0 {
+                            first_moves.push(FirstMove {
+                                hold: held,
+                                pos: reachable.pos,
+                                path: reachable.path.clone(),
+                            });
+                            first_moves.len() - 1
+                        } else {
+                            node.first
+                        };
+                        candidates.push(Candidate {
+                            parent,
+                            fig,
+                            pos: reachable.pos,
+                            hold,
+                            next,
+                            eval: node.eval + eval,
+                            first,
+                        });
+                    }
+                    self.expanded += self.reachable.len();
+                }
+            }
+            if candidates.is_empty() {
+                break;
+            }
+
+            candidates.sort_by(|a, b| b.eval.total_cmp(&a.eval));
+            best = Some((candidates[0].first, candidates[0].eval, depth + 1));
+            // Best candidates first, so a duplicate state is never better
+            // than the one kept
+            self.seen.clear();
+            let mut next_beam = Vec::with_capacity(self.config.beam_width);
+            for candidate in &candidates {
+                if next_beam.len() >= self.config.beam_width.max(1) {
+                    break;
+                }
+                let mut board = beam[candidate.parent].board.clone();
+                figures[candidate.fig].place(&mut board, candidate.pos);
+                for line in board.locked_lines() {
+                    board.throw_line(line);
+                }
+                // The same figure can be held from different places in the
+                // queue
+                let held = candidate
+                    .hold
+                    .map_or(u64::MAX, |hold| zobrist::figure_value(figures[hold]));
+                let key = board.zobrist_hash()
+                    ^ zobrist::state_key(0, held)
+                    ^ zobrist::state_key(1, candidate.next as u64);
+                if !self.seen.insert_new(key, ()) {
+                    continue;
+                }
+                next_beam.push(BeamNode {
+                    board,
+                    hold: candidate.hold,
+                    next: candidate.next,
+                    eval: candidate.eval,
+                    first: candidate.first,
+                });
+            }
+            beam = next_beam;
+        }
+
+        best.map(|(first, eval, depth)| {
+            let first_move = &first_moves[first];
+            BeamChoice {
+                hold: first_move.hold,
+                pos: first_move.pos,
+                path: first_move.path.clone(),
+                eval,
+                depth,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{Bot, LinearEvaluator, Weights};
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+
+    fn fig_o() -> Figure {
+        standard_figures()[3].clone()
+    }
+
+    fn fig_i() -> Figure {
+        standard_figures()[6].clone()
+    }
+
+    // Four rows filled except for a well at x = 9
+    fn well_pf() -> Playfield {
+        let mut pf = Playfield::new("pf", 10, 20);
+        for y in 16..20 {
+            for x in 0..9 {
+                pf.set_block((x, y).into(), Block::Set(1));
+            }
+        }
+        pf
+    }
+
+    fn beam(config: BeamConfig) -> BeamSearch<LinearEvaluator<BitBoard>> {
+        BeamSearch::new(
+            LinearEvaluator::new(Weights::el_tetris()),
+            SearchGravity::moves_per_row(2),
+            config,
+        )
+    }
+
+    #[test]
+    fn one_ply_like_bot() {
+        let pf = well_pf();
+        let start_pos = Position::new((4, 0, 0));
+        let mut bot = Bot::new(
+            LinearEvaluator::new(Weights::el_tetris()),
+            SearchGravity::moves_per_row(2),
+        );
+        let mut search = beam(BeamConfig {
+            depth: 1,
+            use_hold: false,
+            ..BeamConfig::default()
+        });
+        for fig in &[fig_o(), fig_i()] {
+            let bot_choice = bot.choose(&pf, fig, start_pos).unwrap();
+            let choice = search.search(&pf, fig, None, &[], start_pos).unwrap();
+            assert_eq!(choice.pos, bot_choice.pos);
+            assert_eq!(choice.path, bot_choice.path);
+            assert!(!choice.hold);
+            assert_eq!(choice.depth, 1);
+        }
+    }
+
+    #[test]
+    fn hold() {
+        let pf = well_pf();
+        let start_pos = Position::new((4, 0, 0));
+        let mut search = beam(BeamConfig {
+            depth: 1,
+            ..BeamConfig::default()
+        });
+        let tetris = Position::new((8, 16, 0));
+
+        // Swap the O for the I in hold
+        let choice = search
+            .search(&pf, &fig_o(), Some(&fig_i()), &[], start_pos)
+            .unwrap();
+        assert!(choice.hold);
+        assert_eq!(choice.pos, tetris);
+
+        // Hold is empty, hold the O and play the next I
+        let choice = search
+            .search(&pf, &fig_o(), None, &[fig_i()], start_pos)
+            .unwrap();
+        assert!(choice.hold);
+        assert_eq!(choice.pos, tetris);
+
+        // Without hold the O is played and the I kept for the well
+        search.set_config(BeamConfig {
+            depth: 2,
+            use_hold: false,
+            ..BeamConfig::default()
+        });
+        let choice = search
+            .search(&pf, &fig_o(), None, &[fig_i()], start_pos)
+            .unwrap();
+        assert!(!choice.hold);
+        assert!(choice.pos.x() < 8);
+        assert_eq!(choice.depth, 2);
+    }
+
+    // Only placements at the left wall get a score
+    struct LeftWall;
+
+    impl Evaluator<BitBoard> for LeftWall {
+        fn init_eval(&mut self, _: &BitBoard, _: usize) {}
+        fn eval_placing(&mut self, _: &BitBoard, _: &Figure, pos: Position) -> f32 {
+            if pos.x() == 0 {
+                1.0
+            } else {
+                f32::NAN
+            }
+        }
+    }
+
+    #[test]
+    fn nan_evals() {
+        let pf = Playfield::new("pf", 10, 20);
+        let mut search = BeamSearch::new(
+            LeftWall,
+            SearchGravity::moves_per_row(2),
+            BeamConfig::default(),
+        );
+        let choice = search
+            .search(&pf, &fig_o(), None, &[fig_o()], (4, 0, 0).into())
+            .unwrap();
+        assert_eq!(choice.pos.x(), 0);
+        assert_eq!(choice.eval, 2.0);
+        assert_eq!(choice.depth, 2);
+    }
+
+    #[test]
+    fn time_budget() {
+        let pf = well_pf();
+        let mut search = beam(BeamConfig {
+            depth: 3,
+            max_time: Some(Duration::from_secs(0)),
+            ..BeamConfig::default()
+        });
+        let choice = search
+            .search(&pf, &fig_o(), None, &[fig_i(), fig_o()], (4, 0, 0).into())
+            .unwrap();
+        assert_eq!(choice.depth, 1);
+
+        // Game over
+        let mut pf = Playfield::new("pf", 4, 4);
+        pf.set_block((1, 1).into(), Block::Set(1));
+        assert_eq!(
+            search.search(&pf, &fig_o(), None, &[], (1, 0, 0).into()),
+            None
+        );
+    }
+}
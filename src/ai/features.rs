@@ -0,0 +1,278 @@
+use crate::board::Board;
+use crate::figure::Figure;
+use crate::position::Position;
+use crate::regions::Regions;
+
+//
+// Standard features of a board used by evaluators. Heights are counted in
+// rows from the bottom of the board, an empty column has height 0.
+//
+// Transitions are counted the way Dellacherie does: the walls and the
+// floor count as occupied, so an empty row has two row transitions and an
+// empty column one column transition.
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BoardFeatures {
+    pub column_heights: Vec<u32>,
+    pub aggregate_height: u32,
+    pub max_height: u32,
+    // Sum of the height differences between neighbouring columns
+    pub bumpiness: u32,
+    // Free cells with an occupied cell somewhere above in the same column
+    pub holes: u32,
+    // For each hole, the occupied cells above it in the same column
+    pub covered_cells: u32,
+    // Free cells no figure can get to from the top, unlike holes these
+    // can't be filled by sliding in under an overhang
+    pub unreachable_cells: u32,
+    pub row_transitions: u32,
+    pub column_transitions: u32,
+    // Depth of the well in each column, how far below the lowest of its
+    // neighbours (or walls) the column is
+    pub well_depths: Vec<u32>,
+    // Free cells with occupied cells on both sides, each counted by its
+    // depth in the well (1 + 2 + ... + depth for each well)
+    pub cumulative_wells: u32,
+
+    // Cache variable
+    regions: Regions,
+}
+
+impl BoardFeatures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_board<B: Board>(pf: &B) -> Self {
+        let mut features = Self::new();
+        features.compute(pf);
+        features
+    }
+
+    //
+    // Compute all features in one pass over the board, top row first, and
+    // the unreachable cells from the regions of the board
+    //
+    pub fn compute<B: Board>(&mut self, pf: &B) {
+        let width = pf.width() as usize;
+        let height = pf.height();
+        self.column_heights.clear();
+        self.column_heights.resize(width, 0);
+        self.holes = 0;
+        self.covered_cells = 0;
+        self.row_transitions = 0;
+        self.column_transitions = 0;
+        self.cumulative_wells = 0;
+
+        // Per column: occupied cells so far, if the cell above was occupied
+        // and the depth of the current well
+        let mut blocks_above = vec![0; width];
+        let mut above_occupied = vec![false; width];
+        let mut well_run = vec![0; width];
+        for y in 0..height as i32 {
+            let mut last_occupied = true;
+            for x in 0..width {
+                let occupied = pf.occupied((x as i32, y).into());
+                if occupied != last_occupied {
+                    self.row_transitions += 1;
+                }
+                if occupied != above_occupied[x] {
+                    self.column_transitions += 1;
+                }
+                if occupied {
+                    if blocks_above[x] == 0 {
+                        self.column_heights[x] = height - y as u32;
+                    }
+                    blocks_above[x] += 1;
+                    well_run[x] = 0;
+                } else {
+                    if blocks_above[x] > 0 {
+                        self.holes += 1;
+                        self.covered_cells += blocks_above[x];
+                    }
+                    if pf.occupied((x as i32 - 1, y).into())
+                        && pf.occupied((x as i32 + 1, y).into())
+                    {
+                        well_run[x] += 1;
+                        self.cumulative_wells += well_run[x];
+                    } else {
+                        well_run[x] = 0;
+                    }
+                }
+                last_occupied = occupied;
+                above_occupied[x] = occupied;
+            }
+            if !last_occupied {
+                // Right wall
+                self.row_transitions += 1;
+            }
+        }
+        // Floor
+        self.column_transitions += above_occupied.iter().filter(|o| !**o).count() as u32;
+
+        self.aggregate_height = self.column_heights.iter().sum();
+        self.max_height = self.column_heights.iter().copied().max().unwrap_or(0);
+        self.bumpiness = self
+            .column_heights
+            .windows(2)
+            .map(|pair| (pair[0] as i32 - pair[1] as i32).unsigned_abs())
+            .sum();
+        self.well_depths.clear();
+        for x in 0..width {
+            let left = if x > 0 {
+                self.column_heights[x - 1]
+            } else {
+                height
+            };
+            let right = if x + 1 < width {
+                self.column_heights[x + 1]
+            } else {
+                height
+            };
+            let depth = left.min(right).saturating_sub(self.column_heights[x]);
+            self.well_depths.push(depth);
+        }
+
+        self.regions.compute(pf);
+        self.unreachable_cells = self.regions.unreachable_cells();
+    }
+}
+
+//
+// Features of placing a figure, measured on the board before the figure is
+// placed
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PlacementFeatures {
+    // Height of the middle of the figure, counted in rows from the bottom
+    // with the bottom row spanning 0 to 1
+    pub landing_height: f32,
+    pub lines: u32,
+    // Lines cleared times the figure cells in the cleared lines
+    pub eroded_cells: u32,
+}
+
+impl PlacementFeatures {
+    pub fn new<B: Board>(pf: &B, fig: &Figure, pos: Position) -> Self {
+        let geometry = fig.geometry(pos.dir());
+        let top = pf.height() as i32 - pos.y() - i32::from(geometry.min_y());
+        let bottom = pf.height() as i32 - pos.y() - i32::from(geometry.max_y()) - 1;
+
+        let mut lines = 0;
+        let mut cleared_cells = 0;
+        for row in geometry.min_y()..=geometry.max_y() {
+            let y = pos.y() + i32::from(row);
+            let fig_cells = fig
+                .face(pos.dir())
+                .iter()
+                .filter(|(_, block_y, _)| *block_y == row)
+                .count() as u32;
+            let pf_cells = (0..pf.width() as i32)
+                .filter(|x| pf.occupied((*x, y).into()))
+                .count() as u32;
+            if fig_cells + pf_cells == pf.width() {
+                lines += 1;
+                cleared_cells += fig_cells;
+            }
+        }
+        PlacementFeatures {
+            landing_height: (top + bottom) as f32 / 2.0,
+            lines,
+            eroded_cells: lines * cleared_cells,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+    use crate::playfield::Playfield;
+
+    // Board from rows of '#' (occupied) and '.' (free), top row first
+    fn board(rows: &[&str]) -> Playfield {
+        let mut pf = Playfield::new("pf", rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    pf.set_block((x as i32, y as i32).into(), Block::Set(1));
+                }
+            }
+        }
+        pf
+    }
+
+    #[test]
+    fn empty_board() {
+        let features = BoardFeatures::from_board(&Playfield::new("pf", 4, 3));
+        assert_eq!(features.column_heights, vec![0; 4]);
+        assert_eq!(features.aggregate_height, 0);
+        assert_eq!(features.bumpiness, 0);
+        assert_eq!(features.holes, 0);
+        assert_eq!(features.row_transitions, 3 * 2);
+        assert_eq!(features.column_transitions, 4);
+        assert_eq!(features.well_depths, vec![0; 4]);
+        assert_eq!(features.cumulative_wells, 0);
+    }
+
+    #[test]
+    fn hand_checked() {
+        let pf = board(&[
+            "......", //
+            ".#....", //
+            ".#..#.", //
+            "##.##.", //
+            "#..###", //
+        ]);
+        let features = BoardFeatures::from_board(&pf);
+        assert_eq!(features.column_heights, vec![2, 4, 0, 2, 3, 1]);
+        assert_eq!(features.aggregate_height, 12);
+        assert_eq!(features.max_height, 4);
+        assert_eq!(features.bumpiness, 2 + 4 + 2 + 1 + 2);
+        // (1, 4) below three blocks
+        assert_eq!(features.holes, 1);
+        assert_eq!(features.covered_cells, 3);
+        // The hole can be reached through column 2
+        assert_eq!(features.unreachable_cells, 0);
+        assert_eq!(features.row_transitions, 2 + 4 + 6 + 4 + 2);
+        assert_eq!(features.column_transitions, 1 + 3 + 1 + 1 + 1 + 1);
+        assert_eq!(features.well_depths, vec![2, 0, 2, 0, 0, 2]);
+        // Column 0 at rows 1-2, column 2 at row 3 and column 5 at rows 2-3.
+        // The hole in column 1 is open on one side.
+        assert_eq!(features.cumulative_wells, (1 + 2) + 1 + (1 + 2));
+
+        // Closing column 2 also closes off the hole
+        let mut closed = pf.clone();
+        closed.set_block((2, 2).into(), Block::Set(1));
+        let closed_features = BoardFeatures::from_board(&closed);
+        assert_eq!(closed_features.holes, 3);
+        assert_eq!(closed_features.unreachable_cells, 3);
+
+        // The same features are computed on other boards
+        let mut features2 = BoardFeatures::new();
+        features2.compute(&crate::bitboard::BitBoard::from_playfield(&pf));
+        assert_eq!(features, features2);
+    }
+
+    #[test]
+    fn placement() {
+        let pf = board(&[
+            "....", //
+            "....", //
+            "###.", //
+            "###.", //
+        ]);
+        let fig_i = standard_figures()[6].clone();
+        let features = PlacementFeatures::new(&pf, &fig_i, (2, 0, 0).into());
+        assert_eq!(features.landing_height, 2.0);
+        assert_eq!(features.lines, 2);
+        assert_eq!(features.eroded_cells, 4);
+
+        let fig_o = standard_figures()[3].clone();
+        let features = PlacementFeatures::new(&pf, &fig_o, (0, 0, 0).into());
+        assert_eq!(features.landing_height, 3.0);
+        assert_eq!(features.lines, 0);
+        assert_eq!(features.eroded_cells, 0);
+    }
+}
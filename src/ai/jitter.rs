@@ -0,0 +1,85 @@
+use crate::ai::features::BoardFeatures;
+use crate::ai::Evaluator;
+use crate::board::Board;
+use crate::figure::Figure;
+use crate::position::Position;
+
+//
+// Evaluator preferring low and smooth boards, measured by how often rows
+// and columns change between occupied and free blocks (jitter)
+//
+pub struct JitterEvaluator<B: Board> {
+    pre_col_jitter: i32,
+    pre_row_jitter: i32,
+    pre_avg_height: f32,
+    pf: Option<B>,
+    features: BoardFeatures,
+}
+impl<B: Board> Default for JitterEvaluator<B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Board> JitterEvaluator<B> {
+    pub fn new() -> Self {
+        JitterEvaluator {
+            pf: None,
+            pre_col_jitter: 0,
+            pre_row_jitter: 0,
+            pre_avg_height: 0.0,
+            features: BoardFeatures::new(),
+        }
+    }
+}
+impl<B: Board + Clone> Evaluator<B> for JitterEvaluator<B> {
+    fn init_eval(&mut self, pf: &B, _: usize) {
+        if self.pf.is_none() {
+            self.pf = Some(pf.clone())
+        }
+        self.features.compute(pf);
+        self.pre_col_jitter = self.features.column_transitions as i32;
+        self.pre_row_jitter = self.features.row_transitions as i32;
+        self.pre_avg_height = self.features.aggregate_height as f32 / pf.width() as f32;
+    }
+
+    fn eval_placing(&mut self, current_pf: &B, fig: &Figure, pos: Position) -> f32 {
+        if let Some(ref mut pf) = self.pf {
+            pf.copy_from(current_pf);
+            fig.place(pf, pos);
+            let mut full_lines = pf.locked_lines();
+            full_lines.sort();
+
+            let full_lines_score = if full_lines.len() >= 4 {
+                // Great things!
+                10.0
+            } else if full_lines.len() == 1 {
+                // Single full line - Not too bad but still a bit unnecessary
+                -2.0
+            } else if full_lines.len() >= 2 {
+                // 2 or 3 lines should be avoided as long as the avarage playfield height is low
+                let factor = 1.0 - (self.pre_avg_height / pf.height() as f32);
+                (4 - full_lines.len()) as f32 * -factor * 3.0
+            } else {
+                // No full lines - Don't care
+                0.0
+            };
+
+            for line in &full_lines {
+                pf.throw_line(*line);
+            }
+
+            let bottom_block = (i32::from(fig.lowest_block(pos.dir())) + pos.y()) / 2;
+
+            // Measure playfield jitter. Lower jitter is better.
+            self.features.compute(pf);
+            let col_jitter = self.features.column_transitions as i32 - self.pre_col_jitter;
+            let row_jitter = self.features.row_transitions as i32 - self.pre_row_jitter;
+            let jitter_score = -(col_jitter * 3 + row_jitter / 2);
+
+            (bottom_block + jitter_score) as f32 + full_lines_score
+        } else {
+            0.0
+        }
+    }
+}
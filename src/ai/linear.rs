@@ -0,0 +1,372 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ai::features::{BoardFeatures, PlacementFeatures};
+use crate::ai::Evaluator;
+use crate::board::Board;
+use crate::figure::Figure;
+use crate::position::Position;
+
+//
+// Features a linear evaluator can weigh. Landing height, lines and eroded
+// cells are measured for the placement, the rest on the board after the
+// figure has been placed and the full lines cleared.
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feature {
+    LandingHeight,
+    Lines,
+    ErodedCells,
+    AggregateHeight,
+    MaxHeight,
+    Bumpiness,
+    Holes,
+    CoveredCells,
+    UnreachableCells,
+    RowTransitions,
+    ColumnTransitions,
+    CumulativeWells,
+}
+
+impl Feature {
+    pub const COUNT: usize = 12;
+    pub const ALL: [Feature; Feature::COUNT] = [
+        Feature::LandingHeight,
+        Feature::Lines,
+        Feature::ErodedCells,
+        Feature::AggregateHeight,
+        Feature::MaxHeight,
+        Feature::Bumpiness,
+        Feature::Holes,
+        Feature::CoveredCells,
+        Feature::UnreachableCells,
+        Feature::RowTransitions,
+        Feature::ColumnTransitions,
+        Feature::CumulativeWells,
+    ];
+
+    // Name used in weight configs
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::LandingHeight => "landing_height",
+            Feature::Lines => "lines",
+            Feature::ErodedCells => "eroded_cells",
+            Feature::AggregateHeight => "aggregate_height",
+            Feature::MaxHeight => "max_height",
+            Feature::Bumpiness => "bumpiness",
+            Feature::Holes => "holes",
+            Feature::CoveredCells => "covered_cells",
+            Feature::UnreachableCells => "unreachable_cells",
+            Feature::RowTransitions => "row_transitions",
+            Feature::ColumnTransitions => "column_transitions",
+            Feature::CumulativeWells => "cumulative_wells",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Feature> {
+        Feature::ALL.iter().copied().find(|f| f.name() == name)
+    }
+
+    fn value(self, placement: &PlacementFeatures, board: &BoardFeatures) -> f32 {
+        match self {
+            Feature::LandingHeight => placement.landing_height,
+            Feature::Lines => placement.lines as f32,
+            Feature::ErodedCells => placement.eroded_cells as f32,
+            Feature::AggregateHeight => board.aggregate_height as f32,
+            Feature::MaxHeight => board.max_height as f32,
+            Feature::Bumpiness => board.bumpiness as f32,
+            Feature::Holes => board.holes as f32,
+            Feature::CoveredCells => board.covered_cells as f32,
+            Feature::UnreachableCells => board.unreachable_cells as f32,
+            Feature::RowTransitions => board.row_transitions as f32,
+            Feature::ColumnTransitions => board.column_transitions as f32,
+            Feature::CumulativeWells => board.cumulative_wells as f32,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeightsError {
+    // Line isn't on the form "name = value"
+    Syntax { line: usize },
+    UnknownFeature { line: usize, name: String },
+    InvalidValue { line: usize },
+}
+
+impl fmt::Display for WeightsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WeightsError::Syntax { line } => write!(f, "line {}: expected name = value", line),
+            WeightsError::UnknownFeature { line, name } => {
+                write!(f, "line {}: unknown feature {}", line, name)
+            }
+            WeightsError::InvalidValue { line } => write!(f, "line {}: invalid weight", line),
+        }
+    }
+}
+
+impl std::error::Error for WeightsError {}
+
+//
+// Weight of each feature. Features not given a weight have weight 0.
+//
+// Weights are read from and written as a config with one "name = value"
+// per line, where empty lines and lines starting with # are ignored.
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Weights {
+    values: [f32; Feature::COUNT],
+}
+
+impl Weights {
+    pub const PRESETS: [&'static str; 3] = ["dellacherie", "el-tetris", "yiyuan-lee"];
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_values(values: [f32; Feature::COUNT]) -> Self {
+        Weights { values }
+    }
+
+    // Weights in the order of Feature::ALL
+    pub fn values(&self) -> &[f32; Feature::COUNT] {
+        &self.values
+    }
+
+    pub fn get(&self, feature: Feature) -> f32 {
+        self.values[feature as usize]
+    }
+
+    pub fn set(&mut self, feature: Feature, weight: f32) {
+        self.values[feature as usize] = weight;
+    }
+
+    fn with(weights: &[(Feature, f32)]) -> Self {
+        let mut w = Self::new();
+        for (feature, weight) in weights {
+            w.set(*feature, *weight);
+        }
+        w
+    }
+
+    //
+    // Pierre Dellacherie's hand tuned weights, as described by Colin Fahey
+    //
+    pub fn dellacherie() -> Self {
+        Self::with(&[
+            (Feature::LandingHeight, -1.0),
+            (Feature::ErodedCells, 1.0),
+            (Feature::RowTransitions, -1.0),
+            (Feature::ColumnTransitions, -1.0),
+            (Feature::Holes, -4.0),
+            (Feature::CumulativeWells, -1.0),
+        ])
+    }
+
+    //
+    // Islam El-Ashi's El-Tetris, Dellacherie's features tuned with PSO
+    //
+    pub fn el_tetris() -> Self {
+        Self::with(&[
+            (Feature::LandingHeight, -4.500_159),
+            (Feature::ErodedCells, 3.418_127),
+            (Feature::RowTransitions, -3.217_888),
+            (Feature::ColumnTransitions, -9.348_695),
+            (Feature::Holes, -7.899_265),
+            (Feature::CumulativeWells, -3.385_597),
+        ])
+    }
+
+    //
+    // Yiyuan Lee's weights tuned with a genetic algorithm
+    //
+    pub fn yiyuan_lee() -> Self {
+        Self::with(&[
+            (Feature::AggregateHeight, -0.510_066),
+            (Feature::Lines, 0.760_666),
+            (Feature::Holes, -0.356_63),
+            (Feature::Bumpiness, -0.184_483),
+        ])
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "dellacherie" => Some(Self::dellacherie()),
+            "el-tetris" => Some(Self::el_tetris()),
+            "yiyuan-lee" => Some(Self::yiyuan_lee()),
+            _ => None,
+        }
+    }
+
+    pub fn eval(&self, placement: &PlacementFeatures, board: &BoardFeatures) -> f32 {
+        Feature::ALL
+            .iter()
+            .zip(self.values.iter())
+            .filter(|(_, weight)| **weight != 0.0)
+            .map(|(feature, weight)| weight * feature.value(placement, board))
+            .sum()
+    }
+}
+
+impl FromStr for Weights {
+    type Err = WeightsError;
+
+    fn from_str(config: &str) -> Result<Self, Self::Err> {
+        let mut weights = Weights::new();
+        for (index, line) in config.lines().enumerate() {
+            let line_nr = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.splitn(2, '=');
+            let (name, value) = match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) => (name.trim(), value.trim()),
+                _ => return Err(WeightsError::Syntax { line: line_nr }),
+            };
+            let feature = Feature::from_name(name).ok_or_else(|| WeightsError::UnknownFeature {
+                line: line_nr,
+                name: name.to_owned(),
+            })?;
+            let weight = value
+                .parse()
+                .map_err(|_| WeightsError::InvalidValue { line: line_nr })?;
+            weights.set(feature, weight);
+        }
+        Ok(weights)
+    }
+}
+
+impl fmt::Display for Weights {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for feature in Feature::ALL.iter() {
+            writeln!(f, "{} = {}", feature.name(), self.get(*feature))?;
+        }
+        Ok(())
+    }
+}
+
+//
+// Evaluator scoring placements by a weighted sum of board features
+//
+pub struct LinearEvaluator<B: Board> {
+    weights: Weights,
+    pf: Option<B>,
+    features: BoardFeatures,
+}
+
+impl<B: Board> LinearEvaluator<B> {
+    pub fn new(weights: Weights) -> Self {
+        LinearEvaluator {
+            weights,
+            pf: None,
+            features: BoardFeatures::new(),
+        }
+    }
+
+    pub fn weights(&self) -> &Weights {
+        &self.weights
+    }
+
+    pub fn set_weights(&mut self, weights: Weights) {
+        self.weights = weights;
+    }
+}
+
+impl<B: Board + Clone> Evaluator<B> for LinearEvaluator<B> {
+    fn init_eval(&mut self, pf: &B, _: usize) {
+        if self.pf.is_none() {
+            self.pf = Some(pf.clone())
+        }
+    }
+
+    fn eval_placing(&mut self, current_pf: &B, fig: &Figure, pos: Position) -> f32 {
+        if let Some(ref mut pf) = self.pf {
+            let placement = PlacementFeatures::new(current_pf, fig, pos);
+            pf.copy_from(current_pf);
+            fig.place(pf, pos);
+            for line in pf.locked_lines() {
+                pf.throw_line(line);
+            }
+            self.features.compute(pf);
+            self.weights.eval(&placement, &self.features)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+    use crate::playfield::Playfield;
+
+    #[test]
+    fn config() {
+        for name in Weights::PRESETS.iter() {
+            let weights = Weights::preset(name).unwrap();
+            assert_eq!(weights.to_string().parse(), Ok(weights));
+        }
+        assert_eq!(Weights::preset("jitter"), None);
+
+        let config = "# Only holes\n\nholes = -2.5\n  lines=1 \n";
+        let weights: Weights = config.parse().unwrap();
+        assert_eq!(weights.get(Feature::Holes), -2.5);
+        assert_eq!(weights.get(Feature::Lines), 1.0);
+        assert_eq!(weights.get(Feature::Bumpiness), 0.0);
+
+        assert_eq!(
+            "holes -2".parse::<Weights>(),
+            Err(WeightsError::Syntax { line: 1 })
+        );
+        assert_eq!(
+            "\nholez = 1".parse::<Weights>(),
+            Err(WeightsError::UnknownFeature {
+                line: 2,
+                name: "holez".to_owned()
+            })
+        );
+        assert_eq!(
+            "holes = many".parse::<Weights>(),
+            Err(WeightsError::InvalidValue { line: 1 })
+        );
+    }
+
+    #[test]
+    fn eval() {
+        // A well at x = 3 two rows deep
+        let mut pf = Playfield::new("pf", 4, 6);
+        for y in 4..6 {
+            for x in 0..3 {
+                pf.set_block((x, y).into(), Block::Set(1));
+            }
+        }
+        let fig_i = standard_figures()[6].clone();
+        let in_well = (2, 2, 0).into();
+        let on_top = (-1, 0, 0).into();
+
+        // Dellacherie by hand: in the well landing height 2 and eroded
+        // cells 2 * 2. After clearing, column 3 has blocks at rows 4-5
+        // which gives 4 empty rows (8 row transitions) and 2 rows with one
+        // transition to each side, and column transitions 1 for each
+        // column.
+        let mut evaluator = LinearEvaluator::new(Weights::dellacherie());
+        evaluator.init_eval(&pf, 2);
+        assert_eq!(
+            evaluator.eval_placing(&pf, &fig_i, in_well),
+            -2.0 + 4.0 - 12.0 - 4.0
+        );
+        assert!(
+            evaluator.eval_placing(&pf, &fig_i, in_well)
+                > evaluator.eval_placing(&pf, &fig_i, on_top)
+        );
+
+        // Yiyuan Lee, the figure in the well leaves the column 2 high
+        evaluator.set_weights(Weights::yiyuan_lee());
+        let expected = -0.510_066 * 2.0 + 0.760_666 * 2.0 - 0.184_483 * 2.0;
+        assert!((evaluator.eval_placing(&pf, &fig_i, in_well) - expected).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,535 @@
+use std::time::{Duration, Instant};
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::ai::{Choice, Evaluator, JitterEvaluator};
+use crate::bitboard::BitBoard;
+use crate::figure::Figure;
+use crate::find_path::{FindPath, Reachable, SearchGravity};
+use crate::find_placement::{find_placement, find_unique_placement, UniquePlacement};
+use crate::movement::Movement;
+use crate::playfield::Playfield;
+use crate::position::Position;
+use crate::randomizer::Randomizer;
+
+//
+// Horizon is the number of figures placed in each simulation, counting
+// the current figure. The search stops after the iterations or when
+// max_time runs out, whichever comes first. Exploration is the UCT
+// constant, values are normalized to 0..1 before it's applied.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MctsConfig {
+    pub iterations: usize,
+    pub max_time: Option<Duration>,
+    pub horizon: usize,
+    pub exploration: f32,
+    pub seed: u64,
+}
+
+impl Default for MctsConfig {
+    fn default() -> Self {
+        MctsConfig {
+            iterations: 1000,
+            max_time: None,
+            horizon: 6,
+            exploration: 1.4,
+            seed: 0,
+        }
+    }
+}
+
+//
+// Visit statistics of a placement of the current figure
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandidateStats {
+    pub pos: Position,
+    pub path: Vec<Movement>,
+    pub visits: u32,
+    // Mean value of the simulations made through the placement
+    pub mean_value: f32,
+}
+
+struct TreeNode {
+    board: BitBoard,
+    // Evaluation of the placement leading to the node
+    eval: f32,
+    visits: u32,
+    value_sum: f32,
+    // Child nodes for each figure, expanded the first time the figure is
+    // to be placed on the node
+    children: Vec<Option<Vec<usize>>>,
+}
+
+impl TreeNode {
+    fn new(board: BitBoard, eval: f32, num_figures: usize) -> Self {
+        TreeNode {
+            board,
+            eval,
+            visits: 0,
+            value_sum: 0.0,
+            children: vec![None; num_figures],
+        }
+    }
+}
+
+//
+// Monte Carlo tree search over placements of the current figure, the
+// visible next figures and figures sampled from the randomizer beyond
+// them. The tree branches on the placements of each figure, and on the
+// figure when it's sampled.
+//
+// A simulation scores a line of play by the sum of the evaluator scores of
+// its placements. Unvisited placements are scored by a rollout placing the
+// rest of the figures greedily by the rollout evaluator, which is run far
+// more often and should be cheap. Running out of space is scored as the
+// worst simulation so far.
+//
+// Placements of the current figure come from the path search so that
+// they are reachable, deeper placements come from find_unique_placement.
+//
+pub struct MctsBot<E, R = JitterEvaluator<BitBoard>>
+where
+    E: Evaluator<BitBoard>,
+    R: Evaluator<BitBoard>,
+{
+    evaluator: E,
+    rollout_evaluator: R,
+    gravity: SearchGravity,
+    config: MctsConfig,
+    candidates: Vec<CandidateStats>,
+
+    // Some cache variables
+    nodes: Vec<TreeNode>,
+    find_path: FindPath,
+    reachable: Vec<Reachable>,
+    unique_placements: Vec<UniquePlacement>,
+    placements: Vec<Position>,
+    min_value: f32,
+    max_value: f32,
+}
+
+impl<E, R> MctsBot<E, R>
+where
+    E: Evaluator<BitBoard>,
+    R: Evaluator<BitBoard>,
+{
+    pub fn new(
+        evaluator: E,
+        rollout_evaluator: R,
+        gravity: SearchGravity,
+        config: MctsConfig,
+    ) -> Self {
+        MctsBot {
+            evaluator,
+            rollout_evaluator,
+            gravity,
+            config,
+            candidates: Vec::new(),
+            nodes: Vec::new(),
+            find_path: FindPath::new(),
+            reachable: Vec::new(),
+            unique_placements: Vec::new(),
+            placements: Vec::new(),
+            min_value: 0.0,
+            max_value: 0.0,
+        }
+    }
+
+    pub fn config(&self) -> &MctsConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: MctsConfig) {
+        self.config = config;
+    }
+
+    pub fn evaluator(&self) -> &E {
+        &self.evaluator
+    }
+
+    pub fn evaluator_mut(&mut self) -> &mut E {
+        &mut self.evaluator
+    }
+
+    pub fn rollout_evaluator_mut(&mut self) -> &mut R {
+        &mut self.rollout_evaluator
+    }
+
+    pub fn find_path_mut(&mut self) -> &mut FindPath {
+        &mut self.find_path
+    }
+
+    //
+    // Statistics of each placement of the current figure in the last search
+    //
+    pub fn candidates(&self) -> &[CandidateStats] {
+        &self.candidates
+    }
+
+    //
+    // Choose where to place figures[current]. Next are the visible figures
+    // after it, and randomizer is expected to be in the state after next
+    // were dealt. Returns the most visited placement, None if the figure
+    // can't be placed anywhere. Placements evaluated to NaN are skipped
+    // throughout the search.
+    //
+    pub fn choose(
+        &mut self,
+        pf: &Playfield,
+        figures: &[Figure],
+        current: usize,
+        next: &[usize],
+        randomizer: &Randomizer,
+        start_pos: Position,
+    ) -> Option<Choice> {
+        let deadline = self.config.max_time.map(|time| Instant::now() + time);
+        let mut rng = SmallRng::seed_from_u64(self.config.seed);
+        self.nodes.clear();
+        self.candidates.clear();
+        self.min_value = f32::INFINITY;
+        self.max_value = f32::NEG_INFINITY;
+
+        // The root is expanded with the reachable placements
+        let board = BitBoard::from_playfield(pf);
+        let fig = &figures[current];
+        self.find_path
+            .search_all(&mut self.reachable, &board, fig, start_pos, self.gravity);
+        if self.reachable.is_empty() {
+            return None;
+        }
+        self.nodes
+            .push(TreeNode::new(board.clone(), 0.0, figures.len()));
+        self.evaluator.init_eval(&board, self.reachable.len());
+        let mut root_children = Vec::new();
+        for reachable in &self.reachable {
+            let eval = self.evaluator.eval_placing(&board, fig, reachable.pos);
+            if eval.is_nan() {
+                continue;
+            }
+            root_children.push(self.nodes.len());
+            self.nodes.push(TreeNode::new(
+                Self::placed(&board, fig, reachable.pos),
+                eval,
+                figures.len(),
+            ));
+            self.candidates.push(CandidateStats {
+                pos: reachable.pos,
+                path: reachable.path.clone(),
+                visits: 0,
+                mean_value: 0.0,
+            });
+        }
+        if root_children.is_empty() {
+            return None;
+        }
+        self.nodes[0].children[current] = Some(root_children.clone());
+
+        let mut visible = vec![current];
+        visible.extend_from_slice(next);
+        let mut path = Vec::new();
+        for _ in 0..self.config.iterations.max(1) {
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            let mut randomizer = randomizer.clone();
+            path.clear();
+            path.push(0);
+            let mut value = Some(0.0);
+            for depth in 0..self.config.horizon.max(1) {
+                let figure = match visible.get(depth) {
+                    Some(figure) => *figure,
+                    None => randomizer.next(&mut rng),
+                };
+                let node = *path.last().unwrap();
+                let children = self.expand(node, figures, figure);
+                if children.is_empty() {
+                    value = None;
+                    break;
+                }
+                let child = self.select(node, &children);
+                path.push(child);
+                value = value.map(|value| value + self.nodes[child].eval);
+                if self.nodes[child].visits == 0 {
+                    value = value.and_then(|value| {
+                        let rollout = self.rollout(
+                            child,
+                            figures,
+                            &visible[(depth + 1).min(visible.len())..],
+                            &mut randomizer,
+                            &mut rng,
+                            self.config.horizon.max(1) - depth - 1,
+                        )?;
+                        Some(value + rollout)
+                    });
+                    break;
+                }
+            }
+
+            // Running out of space is as bad as the worst line so far
+            let value = match value {
+                Some(value) => value,
+                None if self.min_value.is_finite() => self.min_value,
+                None => 0.0,
+            };
+            self.min_value = self.min_value.min(value);
+            self.max_value = self.max_value.max(value);
+            for node in &path {
+                self.nodes[*node].visits += 1;
+                self.nodes[*node].value_sum += value;
+            }
+        }
+
+        for (candidate, node) in self.candidates.iter_mut().zip(root_children.iter()) {
+            let node = &self.nodes[*node];
+            candidate.visits = node.visits;
+            if node.visits > 0 {
+                candidate.mean_value = node.value_sum / node.visits as f32;
+            }
+        }
+        let best = (0..self.candidates.len()).max_by(|a, b| {
+            let (a, b) = (&self.candidates[*a], &self.candidates[*b]);
+            a.visits
+                .cmp(&b.visits)
+                .then(a.mean_value.total_cmp(&b.mean_value))
+        })?;
+        let best = &self.candidates[best];
+        Some(Choice {
+            pos: best.pos,
+            path: best.path.clone(),
+            eval: best.mean_value,
+        })
+    }
+
+    fn placed(board: &BitBoard, fig: &Figure, pos: Position) -> BitBoard {
+        let mut board = board.clone();
+        fig.place(&mut board, pos);
+        for line in board.locked_lines() {
+            board.throw_line(line);
+        }
+        board
+    }
+
+    // Children of node for placing figure, expanded the first time
+    fn expand(&mut self, node: usize, figures: &[Figure], figure: usize) -> Vec<usize> {
+        if let Some(ref children) = self.nodes[node].children[figure] {
+            return children.clone();
+        }
+        let fig = &figures[figure];
+        let board = self.nodes[node].board.clone();
+        self.unique_placements.clear();
+        find_unique_placement(&mut self.unique_placements, &board, fig);
+        self.evaluator
+            .init_eval(&board, self.unique_placements.len());
+        let mut children = Vec::new();
+        for placement in &self.unique_placements {
+            let pos = placement.positions[0];
+            let eval = self.evaluator.eval_placing(&board, fig, pos);
+            if eval.is_nan() {
+                continue;
+            }
+            children.push(self.nodes.len());
+            self.nodes.push(TreeNode::new(
+                Self::placed(&board, fig, pos),
+                eval,
+                figures.len(),
+            ));
+        }
+        self.nodes[node].children[figure] = Some(children.clone());
+        children
+    }
+
+    // Unvisited children first, then the child with the highest UCT score
+    fn select(&self, node: usize, children: &[usize]) -> usize {
+        if let Some(child) = children.iter().find(|c| self.nodes[**c].visits == 0) {
+            return *child;
+        }
+        let range = self.max_value - self.min_value;
+        let log_visits = (self.nodes[node].visits.max(1) as f32).ln();
+        let score = |child: usize| {
+            let child = &self.nodes[child];
+            let mean = child.value_sum / child.visits as f32;
+            let q = if range > 0.0 {
+                (mean - self.min_value) / range
+            } else {
+                0.5
+            };
+            q + self.config.exploration * (log_visits / child.visits as f32).sqrt()
+        };
+        *children
+            .iter()
+            .max_by(|a, b| score(**a).total_cmp(&score(**b)))
+            .unwrap()
+    }
+
+    //
+    // Place pieces figures greedily by the rollout evaluator from node, the
+    // visible figures first.
+    // Returns the sum of the evaluations, None if a figure can't be placed.
+    //
+    fn rollout(
+        &mut self,
+        node: usize,
+        figures: &[Figure],
+        visible: &[usize],
+        randomizer: &mut Randomizer,
+        rng: &mut SmallRng,
+        pieces: usize,
+    ) -> Option<f32> {
+        let mut board = self.nodes[node].board.clone();
+        let mut value = 0.0;
+        for piece in 0..pieces {
+            let figure = match visible.get(piece) {
+                Some(figure) => *figure,
+                None => randomizer.next(rng),
+            };
+            let fig = &figures[figure];
+            self.placements.clear();
+            find_placement(&mut self.placements, &board, fig);
+            self.rollout_evaluator
+                .init_eval(&board, self.placements.len());
+            let mut best: Option<(Position, f32)> = None;
+            for pos in &self.placements {
+                let eval = self.rollout_evaluator.eval_placing(&board, fig, *pos);
+                if eval.is_nan() {
+                    continue;
+                }
+                if best.is_none_or(|(_, best_eval)| eval > best_eval) {
+                    best = Some((*pos, eval));
+                }
+            }
+            let (pos, eval) = best?;
+            board = Self::placed(&board, fig, pos);
+            value += eval;
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{LinearEvaluator, Weights};
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+    use crate::randomizer::RandomizerKind;
+
+    fn figures() -> Vec<Figure> {
+        let standard = standard_figures();
+        vec![
+            standard[0].clone(),
+            standard[3].clone(),
+            standard[6].clone(),
+        ]
+    }
+
+    fn bot(iterations: usize) -> MctsBot<LinearEvaluator<BitBoard>> {
+        MctsBot::new(
+            LinearEvaluator::new(Weights::el_tetris()),
+            JitterEvaluator::new(),
+            SearchGravity::moves_per_row(2),
+            MctsConfig {
+                iterations,
+                horizon: 3,
+                ..MctsConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn statistics() {
+        // Four rows filled except for a well at x = 9
+        let mut pf = Playfield::new("pf", 10, 20);
+        for y in 16..20 {
+            for x in 0..9 {
+                pf.set_block((x, y).into(), Block::Set(1));
+            }
+        }
+        let figures = figures();
+        let mut randomizer = Randomizer::new(RandomizerKind::Bag, figures.len());
+        randomizer.observe(2);
+        randomizer.observe(0);
+        let start_pos = Position::new((4, 0, 0));
+
+        let mut bot = bot(300);
+        let choice = bot
+            .choose(&pf, &figures, 2, &[0], &randomizer, start_pos)
+            .unwrap();
+        assert_eq!(choice.pos, Position::new((8, 16, 0)));
+
+        let candidates = bot.candidates();
+        assert_eq!(candidates.iter().map(|c| c.visits).sum::<u32>(), 300);
+        let best = candidates.iter().find(|c| c.pos == choice.pos).unwrap();
+        assert!(candidates.iter().all(|c| c.visits <= best.visits));
+        assert_eq!(best.mean_value, choice.eval);
+
+        // Same seed, same search
+        let candidates = candidates.to_vec();
+        bot.choose(&pf, &figures, 2, &[0], &randomizer, start_pos);
+        assert_eq!(bot.candidates(), &candidates[..]);
+
+        // Game over
+        let mut pf = Playfield::new("pf", 4, 4);
+        pf.set_block((1, 1).into(), Block::Set(1));
+        assert_eq!(
+            bot.choose(&pf, &figures, 1, &[], &randomizer, (1, 0, 0).into()),
+            None
+        );
+        assert!(bot.candidates().is_empty());
+    }
+
+    // Only placements at the left wall get a score
+    struct LeftWall;
+
+    impl Evaluator<BitBoard> for LeftWall {
+        fn init_eval(&mut self, _: &BitBoard, _: usize) {}
+        fn eval_placing(&mut self, _: &BitBoard, _: &Figure, pos: Position) -> f32 {
+            if pos.x() == 0 {
+                1.0
+            } else {
+                f32::NAN
+            }
+        }
+    }
+
+    #[test]
+    fn nan_evals() {
+        let pf = Playfield::new("pf", 10, 20);
+        let figures = figures();
+        let randomizer = Randomizer::new(RandomizerKind::Uniform, figures.len());
+        let mut bot = MctsBot::new(
+            LeftWall,
+            LeftWall,
+            SearchGravity::moves_per_row(2),
+            MctsConfig {
+                iterations: 50,
+                horizon: 3,
+                ..MctsConfig::default()
+            },
+        );
+        let choice = bot
+            .choose(&pf, &figures, 1, &[1], &randomizer, (4, 0, 0).into())
+            .unwrap();
+        assert_eq!(choice.pos.x(), 0);
+        assert!(!choice.eval.is_nan());
+        assert!(bot.candidates().iter().all(|c| c.pos.x() == 0));
+    }
+
+    #[test]
+    fn time_budget() {
+        let pf = Playfield::new("pf", 10, 20);
+        let figures = figures();
+        let randomizer = Randomizer::new(RandomizerKind::Uniform, figures.len());
+        let mut bot = bot(1_000_000);
+        bot.set_config(MctsConfig {
+            max_time: Some(Duration::from_millis(50)),
+            ..*bot.config()
+        });
+        let start = Instant::now();
+        let choice = bot.choose(&pf, &figures, 0, &[], &randomizer, (4, 0, 0).into());
+        assert!(choice.is_some());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::bitboard::BitBoard;
+use crate::board::Board;
+use crate::figure::Figure;
+use crate::find_path::{FindPath, Reachable, SearchGravity};
+use crate::movement::Movement;
+use crate::playfield::Playfield;
+use crate::position::Position;
+
+pub mod beam;
+pub mod features;
+pub mod jitter;
+pub mod linear;
+pub mod mcts;
+pub mod sim;
+pub mod transposition;
+pub mod tuner;
+
+pub use self::beam::{BeamChoice, BeamConfig, BeamSearch};
+pub use self::jitter::JitterEvaluator;
+pub use self::linear::{LinearEvaluator, Weights};
+pub use self::mcts::{CandidateStats, MctsBot, MctsConfig};
+pub use self::transposition::TranspositionTable;
+
+//
+// Scores the placements of a figure, a higher score is a better placement
+//
+pub trait Evaluator<B: Board> {
+    //
+    // Called once with the board before the placements of a new figure
+    // are evaluated
+    //
+    fn init_eval(&mut self, pf: &B, avail_placings: usize);
+    fn eval_placing(&mut self, pf: &B, fig: &Figure, pos: Position) -> f32;
+}
+
+impl<B: Board, E: Evaluator<B> + ?Sized> Evaluator<B> for Box<E> {
+    fn init_eval(&mut self, pf: &B, avail_placings: usize) {
+        (**self).init_eval(pf, avail_placings)
+    }
+
+    fn eval_placing(&mut self, pf: &B, fig: &Figure, pos: Position) -> f32 {
+        (**self).eval_placing(pf, fig, pos)
+    }
+}
+
+//
+// Placement chosen by the bot together with the path from the start
+// position, in the order the movements are to be made
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Choice {
+    pub pos: Position,
+    pub path: Vec<Movement>,
+    pub eval: f32,
+}
+
+struct EvalPosition {
+    // Index into the reachable positions
+    index: usize,
+    eval: f32,
+}
+
+//
+// Chooses where to place figures. Every placement reachable from the start
+// position is scored by the evaluator and the best one is chosen. The
+// search and evaluation are made on a bitboard copy of the playfield.
+//
+pub struct Bot<E>
+where
+    E: Evaluator<BitBoard>,
+{
+    evaluator: E,
+    gravity: SearchGravity,
+
+    // Some cache variables
+    board: Option<BitBoard>,
+    reachable: Vec<Reachable>,
+    find_path: FindPath,
+    eval_placing: Vec<EvalPosition>,
+}
+
+impl<E> Bot<E>
+where
+    E: Evaluator<BitBoard>,
+{
+    pub fn new(evaluator: E, gravity: SearchGravity) -> Self {
+        Bot {
+            evaluator,
+            gravity,
+            board: None,
+            reachable: Vec::new(),
+            find_path: FindPath::new(),
+            eval_placing: Vec::new(),
+        }
+    }
+
+    pub fn evaluator(&self) -> &E {
+        &self.evaluator
+    }
+
+    pub fn evaluator_mut(&mut self) -> &mut E {
+        &mut self.evaluator
+    }
+
+    //
+    // Path search used to find the reachable placements, to set its cost
+    // model, kick tables and limits
+    //
+    pub fn find_path_mut(&mut self) -> &mut FindPath {
+        &mut self.find_path
+    }
+
+    //
+    // Number of placements reachable in the last choice
+    //
+    pub fn num_reachable(&self) -> usize {
+        self.reachable.len()
+    }
+
+    //
+    // Choose where to place figure fig starting at pos. None if the figure
+    // can't be placed anywhere. Placements evaluated to NaN are skipped.
+    //
+    pub fn choose(&mut self, pf: &Playfield, fig: &Figure, pos: Position) -> Option<Choice> {
+        match self.board {
+            Some(ref mut board) => board.copy_playfield(pf),
+            None => self.board = Some(BitBoard::from_playfield(pf)),
+        }
+        let board = self.board.as_ref().unwrap();
+
+        // Find all positions where figure can be placed and how to get there
+        self.find_path
+            .search_all(&mut self.reachable, board, fig, pos, self.gravity);
+
+        // Evaluate all placings to find the best one
+        self.evaluator.init_eval(board, self.reachable.len());
+        self.eval_placing.clear();
+        for (index, reachable) in self.reachable.iter().enumerate() {
+            let eval = self.evaluator.eval_placing(board, fig, reachable.pos);
+            if !eval.is_nan() {
+                self.eval_placing.push(EvalPosition { index, eval });
+            }
+        }
+        self.eval_placing.sort_by(|a, b| b.eval.total_cmp(&a.eval));
+
+        self.eval_placing.first().map(|best| {
+            let reachable = &self.reachable[best.index];
+            Choice {
+                pos: reachable.pos,
+                path: reachable.path.clone(),
+                eval: best.eval,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+    use crate::playfield::Gravity;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    // Play pieces figures on an empty board, returns the number of lines
+    fn play<E: Evaluator<BitBoard>>(bot: &mut Bot<E>, seed: u64, pieces: usize) -> usize {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let figures = standard_figures();
+        let mut pf = Playfield::new("pf", 10, 20);
+        let mut lines = 0;
+        for _ in 0..pieces {
+            let fig = &figures[rng.gen_range(0, figures.len())];
+            let start_pos = Position::new((4, 0, 0));
+            let choice = bot.choose(&pf, fig, start_pos).expect("game over");
+
+            let mut pos = start_pos;
+            for movement in &choice.path {
+                pos = pos.apply_move(*movement);
+                pos.normalize_dir(fig.num_faces());
+                assert!(!fig.test_collision(&pf, pos));
+            }
+            assert_eq!(pos, choice.pos);
+            assert!(fig.test_collision(&pf, pos.apply_move(Movement::MoveDown)));
+
+            fig.place(&mut pf, pos);
+            lines += pf
+                .clear_lines(Gravity::Naive)
+                .iter()
+                .map(|step| step.len())
+                .sum::<usize>();
+        }
+        lines
+    }
+
+    // Only placements at the left wall get a score
+    struct LeftWall;
+
+    impl Evaluator<BitBoard> for LeftWall {
+        fn init_eval(&mut self, _: &BitBoard, _: usize) {}
+        fn eval_placing(&mut self, _: &BitBoard, _: &Figure, pos: Position) -> f32 {
+            if pos.x() == 0 {
+                pos.dir() as f32
+            } else {
+                f32::NAN
+            }
+        }
+    }
+
+    #[test]
+    fn nan_evals() {
+        let fig = &standard_figures()[0];
+        let mut bot = Bot::new(LeftWall, SearchGravity::moves_per_row(1));
+        let pf = Playfield::new("pf", 10, 20);
+        let choice = bot.choose(&pf, fig, (4, 0, 0).into()).unwrap();
+        assert_eq!(choice.pos.x(), 0);
+        assert!(!choice.eval.is_nan());
+
+        // Every placement is NaN when the figure lands on a stack at the
+        // left wall before it gets there
+        let mut pf = Playfield::new("pf", 10, 20);
+        for y in 3..20 {
+            pf.set_block((0, y).into(), Block::Set(2));
+            pf.set_block((1, y).into(), Block::Set(2));
+        }
+        assert_eq!(bot.choose(&pf, fig, (4, 0, 0).into()), None);
+    }
+
+    #[test]
+    fn play_headless() {
+        let mut bot = Bot::new(JitterEvaluator::new(), SearchGravity::moves_per_row(1));
+        assert!(play(&mut bot, 43, 100) >= 20);
+    }
+
+    #[test]
+    fn play_headless_linear() {
+        // A baseline that keeps playing
+        let mut bot = Bot::new(
+            LinearEvaluator::new(Weights::el_tetris()),
+            SearchGravity::moves_per_row(1),
+        );
+        assert!(play(&mut bot, 43, 400) >= 150);
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use rand::rngs::SmallRng;
+use rand::SeedableRng;
+
+use crate::ai::features::BoardFeatures;
+use crate::ai::{BeamConfig, BeamSearch, Bot, Evaluator, MctsBot};
+use crate::bitboard::BitBoard;
+use crate::figure::Figure;
+use crate::find_path::{FindPath, SearchGravity};
+use crate::movement::Movement;
+use crate::playfield::{Gravity, Playfield};
+use crate::position::Position;
+use crate::randomizer::{Randomizer, RandomizerKind};
+
+//
+// Rules of a simulated game. The game ends after max_pieces figures at
+// the latest. Gravity is how the figures fall, a placement that can't be
+// reached with it (without kicks) from where the figure appears ends the
+// game.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimConfig {
+    pub width: u32,
+    pub height: u32,
+    // Number of next figures visible to the player
+    pub preview: usize,
+    pub randomizer: RandomizerKind,
+    pub use_hold: bool,
+    pub max_pieces: usize,
+    pub gravity: SearchGravity,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            width: 10,
+            height: 20,
+            preview: 1,
+            randomizer: RandomizerKind::Bag,
+            use_hold: false,
+            max_pieces: 1000,
+            gravity: SearchGravity::moves_per_row(2),
+        }
+    }
+}
+
+//
+// What a player gets to see of the game when a new figure appears.
+// Figures are given as indexes into figures.
+//
+pub struct GameState<'a> {
+    pub pf: &'a Playfield,
+    pub figures: &'a [Figure],
+    pub current: usize,
+    pub next: &'a [usize],
+    // None if hold isn't used in the game
+    pub hold: Option<Option<usize>>,
+    // Randomizer in the state after the visible figures were dealt
+    pub randomizer: &'a Randomizer,
+    pub start_pos: Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimMove {
+    // Hold the current figure and place the figure in hold, or the next
+    // figure if hold is empty
+    pub hold: bool,
+    pub pos: Position,
+}
+
+//
+// Player of simulated games. None gives up the game.
+//
+pub trait SimPlayer {
+    fn play(&mut self, state: &GameState) -> Option<SimMove>;
+}
+
+impl<E: Evaluator<BitBoard>> SimPlayer for Bot<E> {
+    fn play(&mut self, state: &GameState) -> Option<SimMove> {
+        let fig = &state.figures[state.current];
+        let choice = self.choose(state.pf, fig, state.start_pos)?;
+        Some(SimMove {
+            hold: false,
+            pos: choice.pos,
+        })
+    }
+}
+
+impl<E: Evaluator<BitBoard>> SimPlayer for BeamSearch<E> {
+    fn play(&mut self, state: &GameState) -> Option<SimMove> {
+        let next: Vec<Figure> = state
+            .next
+            .iter()
+            .map(|figure| state.figures[*figure].clone())
+            .collect();
+        let hold = state.hold.flatten().map(|figure| &state.figures[figure]);
+        // Only plan with hold if the game has it
+        let config = *self.config();
+        if state.hold.is_none() {
+            self.set_config(BeamConfig {
+                use_hold: false,
+                ..config
+            });
+        }
+        let choice = self.search(
+            state.pf,
+            &state.figures[state.current],
+            hold,
+            &next,
+            state.start_pos,
+        );
+        self.set_config(config);
+        let choice = choice?;
+        Some(SimMove {
+            hold: choice.hold,
+            pos: choice.pos,
+        })
+    }
+}
+
+impl<E: Evaluator<BitBoard>, R: Evaluator<BitBoard>> SimPlayer for MctsBot<E, R> {
+    fn play(&mut self, state: &GameState) -> Option<SimMove> {
+        let choice = self.choose(
+            state.pf,
+            state.figures,
+            state.current,
+            state.next,
+            state.randomizer,
+            state.start_pos,
+        )?;
+        Some(SimMove {
+            hold: false,
+            pos: choice.pos,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameEnd {
+    // All pieces of the game were placed
+    PieceLimit,
+    // A new figure collides where it appears
+    BlockOut,
+    // The player gave up or chose a placement the figure can't reach or
+    // doesn't rest at
+    NoPlacement,
+}
+
+impl fmt::Display for GameEnd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            GameEnd::PieceLimit => "piece-limit",
+            GameEnd::BlockOut => "block-out",
+            GameEnd::NoPlacement => "no-placement",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameResult {
+    pub seed: u64,
+    pub pieces: u32,
+    pub lines: u32,
+    // Number of placements clearing four lines
+    pub tetrises: u32,
+    // Highest the stack has been, in rows
+    pub max_height: u32,
+    pub duration: Duration,
+    pub end: GameEnd,
+}
+
+impl GameResult {
+    pub fn pieces_per_second(&self) -> f32 {
+        let seconds = self.duration.as_secs_f32();
+        if seconds > 0.0 {
+            self.pieces as f32 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    // Share of the lines cleared by tetrises
+    pub fn tetris_rate(&self) -> f32 {
+        if self.lines == 0 {
+            return 0.0;
+        }
+        (self.tetrises * 4) as f32 / self.lines as f32
+    }
+}
+
+//
+// Play a game without graphics. The figures are dealt from a randomizer
+// seeded with seed, so a game can be replayed with the same seed. Figures
+// appear at the top in the middle of the board and are placed where the
+// player chooses, if the figure can get there.
+//
+pub fn play_game<P: SimPlayer + ?Sized>(
+    player: &mut P,
+    figures: &[Figure],
+    config: &SimConfig,
+    seed: u64,
+) -> GameResult {
+    let start = Instant::now();
+    let mut rng = SmallRng::seed_from_u64(seed);
+    let mut randomizer = Randomizer::new(config.randomizer, figures.len());
+    let mut pf = Playfield::new("sim", config.width, config.height);
+    let start_pos = Position::new(((config.width / 2 - 1) as i32, 0, 0));
+    let mut features = BoardFeatures::new();
+    let mut referee = Referee::new();
+
+    let mut queue = VecDeque::new();
+    let mut hold = None;
+    let mut result = GameResult {
+        seed,
+        pieces: 0,
+        lines: 0,
+        tetrises: 0,
+        max_height: 0,
+        duration: Duration::default(),
+        end: GameEnd::PieceLimit,
+    };
+    while (result.pieces as usize) < config.max_pieces {
+        // Only the visible figures are dealt, so the randomizer is in the
+        // state the player can know about
+        while queue.len() < config.preview + 1 {
+            queue.push_back(randomizer.next(&mut rng));
+        }
+        let current = queue.pop_front().unwrap();
+        if figures[current].test_collision(&pf, start_pos) {
+            result.end = GameEnd::BlockOut;
+            break;
+        }
+        let next: Vec<usize> = queue.iter().take(config.preview).copied().collect();
+
+        let state = GameState {
+            pf: &pf,
+            figures,
+            current,
+            next: &next,
+            hold: if config.use_hold { Some(hold) } else { None },
+            randomizer: &randomizer,
+            start_pos,
+        };
+        let placed = match player.play(&state) {
+            Some(SimMove { hold: false, pos }) => Some((current, pos)),
+            Some(SimMove { hold: true, pos }) if config.use_hold => {
+                let figure = match hold {
+                    Some(held) => held,
+                    None => match queue.pop_front() {
+                        Some(figure) => figure,
+                        None => randomizer.next(&mut rng),
+                    },
+                };
+                hold = Some(current);
+                if figures[figure].test_collision(&pf, start_pos) {
+                    result.end = GameEnd::BlockOut;
+                    break;
+                }
+                Some((figure, pos))
+            }
+            _ => None,
+        };
+        let (figure, pos) = match placed {
+            Some((figure, pos))
+                if referee.can_lock(&pf, &figures[figure], start_pos, pos, config.gravity) =>
+            {
+                (figure, pos)
+            }
+            _ => {
+                result.end = GameEnd::NoPlacement;
+                break;
+            }
+        };
+
+        figures[figure].place(&mut pf, pos);
+        features.compute(&pf);
+        result.max_height = result.max_height.max(features.max_height);
+        let lines = pf
+            .clear_lines(Gravity::Naive)
+            .iter()
+            .map(|step| step.len())
+            .sum::<usize>() as u32;
+        result.pieces += 1;
+        result.lines += lines;
+        if lines >= 4 {
+            result.tetrises += 1;
+        }
+    }
+    result.duration = start.elapsed();
+    result
+}
+
+//
+// Checks the placements chosen by the player
+//
+struct Referee {
+    find_path: FindPath,
+    path: Vec<Movement>,
+}
+
+impl Referee {
+    fn new() -> Self {
+        Referee {
+            find_path: FindPath::new(),
+            path: Vec::new(),
+        }
+    }
+
+    //
+    // Test if figure can be moved from start_pos to pos and lock there
+    //
+    fn can_lock(
+        &mut self,
+        pf: &Playfield,
+        fig: &Figure,
+        start_pos: Position,
+        pos: Position,
+        gravity: SearchGravity,
+    ) -> bool {
+        if fig.test_collision(pf, pos) || fig.drop_distance(pf, pos) > 0 {
+            return false;
+        }
+        self.find_path
+            .search(&mut self.path, pf, fig, start_pos, pos, gravity)
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::{LinearEvaluator, Weights};
+    use crate::figure::standard_figures;
+    use crate::find_path::SearchGravity;
+
+    // Drops every figure straight down from where it appears
+    struct DropPlayer;
+
+    impl SimPlayer for DropPlayer {
+        fn play(&mut self, state: &GameState) -> Option<SimMove> {
+            let fig = &state.figures[state.current];
+            let pos = state.start_pos;
+            let distance = fig.drop_distance(state.pf, pos) as i32;
+            Some(SimMove {
+                hold: false,
+                pos: (pos.x(), pos.y() + distance, pos.dir()).into(),
+            })
+        }
+    }
+
+    struct GiveUpPlayer;
+
+    impl SimPlayer for GiveUpPlayer {
+        fn play(&mut self, _: &GameState) -> Option<SimMove> {
+            None
+        }
+    }
+
+    // Places every figure at a column, without moving it down when x is
+    // None, swapping it for the figure to hold first when hold is set
+    struct PlaceAtPlayer {
+        x: Option<i32>,
+        hold: bool,
+    }
+
+    impl SimPlayer for PlaceAtPlayer {
+        fn play(&mut self, state: &GameState) -> Option<SimMove> {
+            let figure = match (self.hold, state.hold) {
+                (true, Some(Some(held))) => held,
+                (true, Some(None)) => state.next[0],
+                _ => state.current,
+            };
+            let fig = &state.figures[figure];
+            let pos = match self.x {
+                Some(x) => {
+                    let pos = Position::new((x, 0, 0));
+                    let distance = fig.drop_distance(state.pf, pos) as i32;
+                    (x, distance, 0).into()
+                }
+                None => state.start_pos,
+            };
+            Some(SimMove {
+                hold: self.hold,
+                pos,
+            })
+        }
+    }
+
+    #[test]
+    fn game_end() {
+        let figures = standard_figures();
+        let config = SimConfig {
+            width: 6,
+            height: 8,
+            ..SimConfig::default()
+        };
+        let result = play_game(&mut DropPlayer, &figures, &config, 46);
+        assert_eq!(result.end, GameEnd::BlockOut);
+        assert!(result.pieces > 0);
+        assert_eq!(result.lines, 0);
+        assert!(result.max_height >= 6);
+
+        let result = play_game(&mut GiveUpPlayer, &figures, &config, 46);
+        assert_eq!(result.end, GameEnd::NoPlacement);
+        assert_eq!(result.pieces, 0);
+
+        // Left in the air where it appears
+        let mut player = PlaceAtPlayer {
+            x: None,
+            hold: false,
+        };
+        let result = play_game(&mut player, &figures, &config, 46);
+        assert_eq!(result.end, GameEnd::NoPlacement);
+        assert_eq!(result.pieces, 0);
+
+        // The left wall is out of reach with a single move before landing
+        let mut player = PlaceAtPlayer {
+            x: Some(0),
+            hold: false,
+        };
+        let result = play_game(&mut player, &figures, &config, 46);
+        assert!(result.pieces > 0);
+        let config = SimConfig {
+            gravity: SearchGravity::MovesPerRow { moves: 1, rows: 8 },
+            ..config
+        };
+        let result = play_game(&mut player, &figures, &config, 46);
+        assert_eq!(result.end, GameEnd::NoPlacement);
+        assert_eq!(result.pieces, 0);
+
+        // The figure swapped in from hold doesn't fit where it appears
+        let config = SimConfig {
+            use_hold: true,
+            ..SimConfig::default()
+        };
+        let mut player = PlaceAtPlayer {
+            x: Some(4),
+            hold: true,
+        };
+        for seed in 0..10 {
+            let result = play_game(&mut player, &figures, &config, seed);
+            assert_eq!(result.end, GameEnd::BlockOut);
+        }
+    }
+
+    #[test]
+    fn bots() {
+        let figures = standard_figures();
+        let config = SimConfig {
+            max_pieces: 100,
+            ..SimConfig::default()
+        };
+        let gravity = SearchGravity::moves_per_row(2);
+        let mut bot = Bot::new(LinearEvaluator::new(Weights::el_tetris()), gravity);
+        let result = play_game(&mut bot, &figures, &config, 46);
+        assert_eq!(result.end, GameEnd::PieceLimit);
+        assert_eq!(result.pieces, 100);
+        assert!(result.lines >= 30);
+        assert!(result.tetris_rate() >= 0.0 && result.tetris_rate() <= 1.0);
+        assert_eq!(
+            play_game(&mut bot, &figures, &config, 46).lines,
+            result.lines
+        );
+
+        // Planning with hold
+        let config = SimConfig {
+            use_hold: true,
+            max_pieces: 50,
+            ..config
+        };
+        let mut beam = BeamSearch::new(
+            LinearEvaluator::new(Weights::el_tetris()),
+            gravity,
+            BeamConfig {
+                beam_width: 4,
+                ..BeamConfig::default()
+            },
+        );
+        let result = play_game(&mut beam, &figures, &config, 46);
+        assert_eq!(result.end, GameEnd::PieceLimit);
+        assert!(result.lines >= 15);
+    }
+}
@@ -0,0 +1,123 @@
+//
+// Fixed size table of values for search states, keyed by a hash of the
+// state (see zobrist). Each key has one slot, a new entry replaces the one
+// in its slot, so the table never grows and an entry may be forgotten.
+// Entries keep the full key, a lookup never returns the value of another
+// state with the same slot.
+//
+#[derive(Debug, Clone)]
+pub struct TranspositionTable<V> {
+    entries: Vec<Option<(u64, V)>>,
+    len: usize,
+    hits: usize,
+    misses: usize,
+}
+
+impl<V> TranspositionTable<V> {
+    //
+    // Capacity is rounded up to a power of two
+    //
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        TranspositionTable {
+            entries: (0..capacity).map(|_| None).collect(),
+            len: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Lookups since the table was created or cleared that found an entry
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        // The low bits of zobrist hashes are as random as the high bits
+        key as usize & (self.entries.len() - 1)
+    }
+
+    pub fn get(&mut self, key: u64) -> Option<&V> {
+        let slot = self.slot(key);
+        match self.entries[slot] {
+            Some((entry_key, ref value)) if entry_key == key => {
+                self.hits += 1;
+                Some(value)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: u64, value: V) {
+        let slot = self.slot(key);
+        if self.entries[slot].is_none() {
+            self.len += 1;
+        }
+        self.entries[slot] = Some((key, value));
+    }
+
+    //
+    // Insert the value unless the state is already in the table. Returns
+    // true if the state is new, search uses it to skip duplicate states.
+    //
+    pub fn insert_new(&mut self, key: u64, value: V) -> bool {
+        if self.get(key).is_some() {
+            return false;
+        }
+        self.insert(key, value);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        for entry in &mut self.entries {
+            *entry = None;
+        }
+        self.len = 0;
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table() {
+        let mut table = TranspositionTable::new(5);
+        assert_eq!(table.capacity(), 8);
+        assert!(table.is_empty());
+        table.insert(3, "a");
+        assert_eq!(table.get(3), Some(&"a"));
+        // Same slot, different key
+        assert_eq!(table.get(11), None);
+        assert!(table.insert_new(11, "b"));
+        assert_eq!(table.get(3), None);
+        assert!(!table.insert_new(11, "c"));
+        assert_eq!(table.get(11), Some(&"b"));
+        assert_eq!(table.len(), 1);
+        assert_eq!((table.hits(), table.misses()), (3, 3));
+        table.clear();
+        assert!(table.is_empty());
+        assert_eq!(table.get(11), None);
+    }
+}
@@ -0,0 +1,458 @@
+use std::fmt;
+use std::thread;
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use crate::ai::linear::{Feature, Weights, WeightsError};
+use crate::ai::sim::{play_game, SimConfig};
+use crate::ai::{Bot, LinearEvaluator};
+use crate::figure::standard_figures;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TuneMethod {
+    // Cross-entropy method. Candidates are sampled from a normal
+    // distribution per weight, which is refitted to the elite fraction of
+    // the candidates. Noise is added to the variance to keep it from
+    // collapsing too early.
+    CrossEntropy {
+        elite_fraction: f32,
+        noise: f32,
+    },
+    // Genetic algorithm. The elite best candidates are kept and the rest
+    // are children of parents picked by tournament selection, made by
+    // uniform crossover and mutated with the rate and scale.
+    Genetic {
+        elite: usize,
+        tournament: usize,
+        mutation_rate: f32,
+        mutation_scale: f32,
+    },
+}
+
+impl TuneMethod {
+    pub fn cross_entropy() -> Self {
+        TuneMethod::CrossEntropy {
+            elite_fraction: 0.1,
+            noise: 0.1,
+        }
+    }
+
+    pub fn genetic() -> Self {
+        TuneMethod::Genetic {
+            elite: 2,
+            tournament: 3,
+            mutation_rate: 0.2,
+            mutation_scale: 0.3,
+        }
+    }
+}
+
+//
+// Candidates are scored by the mean lines cleared by a greedy bot with the
+// linear evaluator over a number of games. All candidates of a generation
+// play the same games. Only the weights of features are tuned, the rest
+// stay 0.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunerConfig {
+    pub method: TuneMethod,
+    pub population: usize,
+    pub games: usize,
+    pub sim: SimConfig,
+    pub features: Vec<Feature>,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+impl Default for TunerConfig {
+    fn default() -> Self {
+        TunerConfig {
+            method: TuneMethod::cross_entropy(),
+            population: 50,
+            games: 4,
+            sim: SimConfig {
+                max_pieces: 500,
+                ..SimConfig::default()
+            },
+            features: vec![
+                Feature::LandingHeight,
+                Feature::ErodedCells,
+                Feature::RowTransitions,
+                Feature::ColumnTransitions,
+                Feature::Holes,
+                Feature::CumulativeWells,
+            ],
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenerationStats {
+    pub generation: u32,
+    pub best_fitness: f32,
+    pub mean_fitness: f32,
+    pub best: Weights,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckpointError {
+    // Line outside of a section that isn't a known "name = value"
+    Syntax { line: usize },
+    UnknownSection { section: String },
+    Weights { section: String, err: WeightsError },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Syntax { line } => write!(f, "line {}: invalid checkpoint", line),
+            CheckpointError::UnknownSection { section } => {
+                write!(f, "unknown section {}", section)
+            }
+            CheckpointError::Weights { section, err } => {
+                write!(f, "section {}: {}", section, err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+//
+// Tunes the weights of a linear evaluator one generation at a time. A
+// generation depends only on the state after the previous one and the
+// seed, so a run can be saved as a checkpoint and resumed with the same
+// result, with any number of threads.
+//
+pub struct Tuner {
+    config: TunerConfig,
+    generation: u32,
+    // Distribution of the cross-entropy method
+    mean: Weights,
+    std_dev: Weights,
+    // Population of the genetic algorithm, empty before the first
+    // generation
+    population: Vec<Weights>,
+    best: Weights,
+    best_fitness: f32,
+}
+
+impl Tuner {
+    pub fn new(config: TunerConfig) -> Self {
+        let mut std_dev = Weights::new();
+        for feature in &config.features {
+            std_dev.set(*feature, 1.0);
+        }
+        Tuner {
+            config,
+            generation: 0,
+            mean: Weights::new(),
+            std_dev,
+            population: Vec::new(),
+            best: Weights::new(),
+            best_fitness: f32::NEG_INFINITY,
+        }
+    }
+
+    pub fn config(&self) -> &TunerConfig {
+        &self.config
+    }
+
+    // Number of generations run
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    //
+    // Best candidate found in all generations
+    //
+    pub fn best(&self) -> &Weights {
+        &self.best
+    }
+
+    pub fn best_fitness(&self) -> f32 {
+        self.best_fitness
+    }
+
+    //
+    // Run one generation
+    //
+    pub fn step(&mut self) -> GenerationStats {
+        let mut rng = SmallRng::seed_from_u64(
+            self.config.seed ^ u64::from(self.generation + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+        );
+        let candidates: Vec<Weights> = match self.config.method {
+            TuneMethod::CrossEntropy { .. } => (0..self.config.population)
+                .map(|_| {
+                    let mut weights = self.mean;
+                    for feature in &self.config.features {
+                        let weight =
+                            self.mean.get(*feature) + self.std_dev.get(*feature) * normal(&mut rng);
+                        weights.set(*feature, weight);
+                    }
+                    weights
+                })
+                .collect(),
+            TuneMethod::Genetic { .. } if self.population.is_empty() => (0..self.config.population)
+                .map(|_| {
+                    let mut weights = Weights::new();
+                    for feature in &self.config.features {
+                        weights.set(*feature, rng.gen_range(-1.0, 1.0));
+                    }
+                    normalized(weights)
+                })
+                .collect(),
+            TuneMethod::Genetic { .. } => self.population.clone(),
+        };
+
+        let fitness = self.evaluate(&candidates);
+        let mut order: Vec<usize> = (0..candidates.len()).collect();
+        order.sort_by(|a, b| fitness[*b].total_cmp(&fitness[*a]));
+        let ranked: Vec<&Weights> = order.iter().map(|i| &candidates[*i]).collect();
+        let best_fitness = fitness[order[0]];
+        if best_fitness > self.best_fitness {
+            self.best_fitness = best_fitness;
+            self.best = *ranked[0];
+        }
+
+        match self.config.method {
+            TuneMethod::CrossEntropy {
+                elite_fraction,
+                noise,
+            } => {
+                let elite = ((ranked.len() as f32 * elite_fraction).round() as usize).max(1);
+                for feature in &self.config.features {
+                    let values: Vec<f32> =
+                        ranked[..elite].iter().map(|w| w.get(*feature)).collect();
+                    let mean = values.iter().sum::<f32>() / elite as f32;
+                    let variance =
+                        values.iter().map(|v| (v - mean) * (v - mean)).sum::<f32>() / elite as f32;
+                    self.mean.set(*feature, mean);
+                    self.std_dev.set(*feature, (variance + noise).sqrt());
+                }
+            }
+            TuneMethod::Genetic {
+                elite,
+                tournament,
+                mutation_rate,
+                mutation_scale,
+            } => {
+                let mut population: Vec<Weights> = ranked.iter().take(elite).map(|w| **w).collect();
+                // Tournament picks the best of a few random candidates,
+                // which is the one ranked highest
+                let pick = |rng: &mut SmallRng| {
+                    let rank = (0..tournament.max(1))
+                        .map(|_| rng.gen_range(0, ranked.len()))
+                        .min()
+                        .unwrap();
+                    ranked[rank]
+                };
+                while population.len() < self.config.population {
+                    let (a, b) = (pick(&mut rng), pick(&mut rng));
+                    let mut child = Weights::new();
+                    for feature in &self.config.features {
+                        let mut weight = if rng.gen::<bool>() {
+                            a.get(*feature)
+                        } else {
+                            b.get(*feature)
+                        };
+                        if rng.gen::<f32>() < mutation_rate {
+                            weight += mutation_scale * normal(&mut rng);
+                        }
+                        child.set(*feature, weight);
+                    }
+                    population.push(normalized(child));
+                }
+                self.population = population;
+            }
+        }
+
+        self.generation += 1;
+        GenerationStats {
+            generation: self.generation,
+            best_fitness,
+            mean_fitness: fitness.iter().sum::<f32>() / fitness.len() as f32,
+            best: *ranked[0],
+        }
+    }
+
+    // Seeds of the games played by every candidate in this generation
+    fn game_seeds(&self) -> Vec<u64> {
+        let first = self
+            .config
+            .seed
+            .wrapping_add(u64::from(self.generation) * self.config.games as u64);
+        (0..self.config.games as u64)
+            .map(|game| first.wrapping_add(game))
+            .collect()
+    }
+
+    fn evaluate(&self, candidates: &[Weights]) -> Vec<f32> {
+        let seeds = self.game_seeds();
+        let config = &self.config;
+        let mut fitness = vec![0.0; candidates.len()];
+        let chunk = candidates.len().div_ceil(config.threads.max(1)).max(1);
+        thread::scope(|scope| {
+            for (candidates, fitness) in candidates.chunks(chunk).zip(fitness.chunks_mut(chunk)) {
+                let seeds = &seeds;
+                scope.spawn(move || {
+                    let figures = standard_figures();
+                    for (weights, fitness) in candidates.iter().zip(fitness.iter_mut()) {
+                        let mut bot = Bot::new(LinearEvaluator::new(*weights), config.sim.gravity);
+                        let lines: u32 = seeds
+                            .iter()
+                            .map(|seed| play_game(&mut bot, &figures, &config.sim, *seed).lines)
+                            .sum();
+                        *fitness = lines as f32 / seeds.len().max(1) as f32;
+                    }
+                });
+            }
+        });
+        fitness
+    }
+
+    //
+    // State of the run as text, to be resumed with from_checkpoint
+    //
+    pub fn checkpoint(&self) -> String {
+        let mut text = String::new();
+        text += &format!("generation = {}\n", self.generation);
+        text += &format!("best_fitness = {}\n", self.best_fitness);
+        text += &format!("[best]\n{}", self.best);
+        text += &format!("[mean]\n{}", self.mean);
+        text += &format!("[std_dev]\n{}", self.std_dev);
+        for member in &self.population {
+            text += &format!("[member]\n{}", member);
+        }
+        text
+    }
+
+    //
+    // Resume a run from a checkpoint. The config is expected to be the one
+    // the checkpoint was made with.
+    //
+    pub fn from_checkpoint(config: TunerConfig, checkpoint: &str) -> Result<Self, CheckpointError> {
+        let mut tuner = Tuner::new(config);
+        let mut sections: Vec<(String, String)> = Vec::new();
+        for (index, line) in checkpoint.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                sections.push((trimmed[1..trimmed.len() - 1].to_owned(), String::new()));
+            } else if let Some((_, text)) = sections.last_mut() {
+                text.push_str(line);
+                text.push('\n');
+            } else if !trimmed.is_empty() {
+                let syntax = CheckpointError::Syntax { line: index + 1 };
+                let mut parts = trimmed.splitn(2, '=').map(str::trim);
+                match (parts.next(), parts.next()) {
+                    (Some("generation"), Some(value)) => {
+                        tuner.generation = value.parse().map_err(|_| syntax)?
+                    }
+                    (Some("best_fitness"), Some(value)) => {
+                        tuner.best_fitness = value.parse().map_err(|_| syntax)?
+                    }
+                    _ => return Err(syntax),
+                }
+            }
+        }
+        for (section, text) in sections {
+            let weights = text.parse().map_err(|err| CheckpointError::Weights {
+                section: section.clone(),
+                err,
+            })?;
+            match section.as_str() {
+                "best" => tuner.best = weights,
+                "mean" => tuner.mean = weights,
+                "std_dev" => tuner.std_dev = weights,
+                "member" => tuner.population.push(weights),
+                _ => return Err(CheckpointError::UnknownSection { section }),
+            }
+        }
+        Ok(tuner)
+    }
+}
+
+// Sample of the standard normal distribution (Box-Muller)
+fn normal<R: Rng>(rng: &mut R) -> f32 {
+    let u1: f32 = 1.0 - rng.gen::<f32>();
+    let u2: f32 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f32::consts::PI * u2).cos()
+}
+
+// Scaled to unit length, the scale doesn't change which placement is best
+fn normalized(weights: Weights) -> Weights {
+    let length = weights.values().iter().map(|w| w * w).sum::<f32>().sqrt();
+    if length == 0.0 {
+        return weights;
+    }
+    let mut values = *weights.values();
+    for value in values.iter_mut() {
+        *value /= length;
+    }
+    Weights::from_values(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(method: TuneMethod, threads: usize) -> TunerConfig {
+        TunerConfig {
+            method,
+            population: 6,
+            games: 1,
+            sim: SimConfig {
+                width: 6,
+                height: 12,
+                max_pieces: 30,
+                ..SimConfig::default()
+            },
+            threads,
+            seed: 47,
+            ..TunerConfig::default()
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        for method in &[TuneMethod::cross_entropy(), TuneMethod::genetic()] {
+            let mut single = Tuner::new(config(*method, 1));
+            let mut threaded = Tuner::new(config(*method, 4));
+            for _ in 0..2 {
+                assert_eq!(single.step(), threaded.step());
+            }
+            assert_eq!(single.best(), threaded.best());
+            assert!(single.best_fitness() > 0.0);
+            for feature in Feature::ALL.iter() {
+                if !single.config().features.contains(feature) {
+                    assert_eq!(single.best().get(*feature), 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resume() {
+        for method in &[TuneMethod::cross_entropy(), TuneMethod::genetic()] {
+            let mut tuner = Tuner::new(config(*method, 2));
+            tuner.step();
+            let checkpoint = tuner.checkpoint();
+            let mut resumed = Tuner::from_checkpoint(config(*method, 2), &checkpoint).unwrap();
+            assert_eq!(resumed.generation(), 1);
+            assert_eq!(resumed.checkpoint(), checkpoint);
+            assert_eq!(tuner.step(), resumed.step());
+
+            // The best weights can be loaded by the evaluator
+            let weights: Weights = tuner.best().to_string().parse().unwrap();
+            assert_eq!(&weights, tuner.best());
+        }
+
+        assert_eq!(
+            Tuner::from_checkpoint(config(TuneMethod::genetic(), 1), "generation 1").err(),
+            Some(CheckpointError::Syntax { line: 1 })
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use std::process;
+
+use rstris::ai::sim::*;
+use rstris::ai::*;
+use rstris::bitboard::BitBoard;
+use rstris::figure::standard_figures;
+use rstris::find_path::SearchGravity;
+use rstris::randomizer::RandomizerKind;
+
+static USAGE: &str = "\
+Usage: rstris-sim [options]
+
+Plays seeded games without graphics and reports how the bot did.
+
+Options:
+  --games N           number of games (10)
+  --seed N            seed of the first game, the following games use the
+                      next seeds (0)
+  --pieces N          max pieces per game (1000)
+  --bot NAME          greedy, beam or mcts (greedy)
+  --eval NAME         jitter, dellacherie, el-tetris, yiyuan-lee or a
+                      weights config file (el-tetris)
+  --width N           board width (10)
+  --height N          board height (20)
+  --preview N         visible next figures (1)
+  --randomizer NAME   bag or uniform (bag)
+  --hold              allow hold
+  --moves-per-row N   movements the figure can make per row it falls (2)
+  --depth N           beam search depth (2)
+  --beam-width N      beam search width (16)
+  --iterations N      mcts iterations (1000)
+  --format NAME       table, csv or json (table)
+  --help              show this text
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+struct Options {
+    games: u64,
+    seed: u64,
+    bot: String,
+    eval: String,
+    sim: SimConfig,
+    beam: BeamConfig,
+    mcts: MctsConfig,
+    format: Format,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, name))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        games: 10,
+        seed: 0,
+        bot: "greedy".to_owned(),
+        eval: "el-tetris".to_owned(),
+        sim: SimConfig::default(),
+        beam: BeamConfig::default(),
+        mcts: MctsConfig::default(),
+        format: Format::Table,
+    };
+    while let Some(arg) = args.next() {
+        let name = arg.as_str();
+        match name {
+            "--games" => options.games = parse_value(name, args.next())?,
+            "--seed" => options.seed = parse_value(name, args.next())?,
+            "--pieces" => options.sim.max_pieces = parse_value(name, args.next())?,
+            "--bot" => options.bot = parse_value(name, args.next())?,
+            "--eval" => options.eval = parse_value(name, args.next())?,
+            "--width" => options.sim.width = parse_value(name, args.next())?,
+            "--height" => options.sim.height = parse_value(name, args.next())?,
+            "--preview" => options.sim.preview = parse_value(name, args.next())?,
+            "--randomizer" => {
+                options.sim.randomizer = match parse_value::<String>(name, args.next())?.as_str() {
+                    "bag" => RandomizerKind::Bag,
+                    "uniform" => RandomizerKind::Uniform,
+                    other => return Err(format!("unknown randomizer {}", other)),
+                }
+            }
+            "--hold" => options.sim.use_hold = true,
+            "--moves-per-row" => {
+                options.sim.gravity = SearchGravity::moves_per_row(parse_value(name, args.next())?)
+            }
+            "--depth" => options.beam.depth = parse_value(name, args.next())?,
+            "--beam-width" => options.beam.beam_width = parse_value(name, args.next())?,
+            "--iterations" => options.mcts.iterations = parse_value(name, args.next())?,
+            "--format" => {
+                options.format = match parse_value::<String>(name, args.next())?.as_str() {
+                    "table" => Format::Table,
+                    "csv" => Format::Csv,
+                    "json" => Format::Json,
+                    other => return Err(format!("unknown format {}", other)),
+                }
+            }
+            "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if options.sim.width < 4 || options.sim.height < 4 {
+        return Err("the board needs to be at least 4x4".to_owned());
+    }
+    Ok(options)
+}
+
+fn make_evaluator(name: &str) -> Result<Box<dyn Evaluator<BitBoard>>, String> {
+    if name == "jitter" {
+        return Ok(Box::new(JitterEvaluator::new()));
+    }
+    let weights = match Weights::preset(name) {
+        Some(weights) => weights,
+        None => {
+            let config = std::fs::read_to_string(name)
+                .map_err(|err| format!("unable to read weights {}: {}", name, err))?;
+            config
+                .parse()
+                .map_err(|err| format!("invalid weights {}: {}", name, err))?
+        }
+    };
+    Ok(Box::new(LinearEvaluator::new(weights)))
+}
+
+fn make_player(options: &Options) -> Result<Box<dyn SimPlayer>, String> {
+    let evaluator = make_evaluator(&options.eval)?;
+    let gravity = options.sim.gravity;
+    let player: Box<dyn SimPlayer> = match options.bot.as_str() {
+        "greedy" => Box::new(Bot::new(evaluator, gravity)),
+        "beam" => Box::new(BeamSearch::new(evaluator, gravity, options.beam)),
+        "mcts" => Box::new(MctsBot::new(
+            evaluator,
+            JitterEvaluator::new(),
+            gravity,
+            options.mcts,
+        )),
+        other => return Err(format!("unknown bot {}", other)),
+    };
+    Ok(player)
+}
+
+//
+// Results of all games together, with the rates over all games
+//
+fn total(results: &[GameResult]) -> GameResult {
+    let mut total = GameResult {
+        seed: 0,
+        pieces: 0,
+        lines: 0,
+        tetrises: 0,
+        max_height: 0,
+        duration: Default::default(),
+        end: GameEnd::PieceLimit,
+    };
+    for result in results {
+        total.pieces += result.pieces;
+        total.lines += result.lines;
+        total.tetrises += result.tetrises;
+        total.max_height = total.max_height.max(result.max_height);
+        total.duration += result.duration;
+    }
+    total
+}
+
+fn print_table(results: &[GameResult]) {
+    println!(
+        "{:>5} {:>12} {:>7} {:>7} {:>8} {:>9} {:>6}  end",
+        "game", "seed", "pieces", "lines", "tetris%", "pieces/s", "max_h"
+    );
+    let row = |game: &str, result: &GameResult, end: &str| {
+        println!(
+            "{:>5} {:>12} {:>7} {:>7} {:>8.1} {:>9.1} {:>6}  {}",
+            game,
+            result.seed,
+            result.pieces,
+            result.lines,
+            result.tetris_rate() * 100.0,
+            result.pieces_per_second(),
+            result.max_height,
+            end
+        );
+    };
+    for (game, result) in results.iter().enumerate() {
+        row(&game.to_string(), result, &result.end.to_string());
+    }
+    let topped_out = results
+        .iter()
+        .filter(|result| result.end != GameEnd::PieceLimit)
+        .count();
+    row(
+        "total",
+        &total(results),
+        &format!("{} of {} topped out", topped_out, results.len()),
+    );
+}
+
+fn print_csv(results: &[GameResult]) {
+    println!("game,seed,pieces,lines,tetrises,tetris_rate,pieces_per_second,max_height,end");
+    for (game, result) in results.iter().enumerate() {
+        println!(
+            "{},{},{},{},{},{:.4},{:.2},{},{}",
+            game,
+            result.seed,
+            result.pieces,
+            result.lines,
+            result.tetrises,
+            result.tetris_rate(),
+            result.pieces_per_second(),
+            result.max_height,
+            result.end
+        );
+    }
+}
+
+fn json_object(result: &GameResult) -> String {
+    format!(
+        "{{\"seed\": {}, \"pieces\": {}, \"lines\": {}, \"tetrises\": {}, \
+         \"tetris_rate\": {:.4}, \"pieces_per_second\": {:.2}, \"max_height\": {}",
+        result.seed,
+        result.pieces,
+        result.lines,
+        result.tetrises,
+        result.tetris_rate(),
+        result.pieces_per_second(),
+        result.max_height
+    )
+}
+
+fn print_json(results: &[GameResult]) {
+    println!("{{");
+    println!("  \"games\": [");
+    for (game, result) in results.iter().enumerate() {
+        let separator = if game + 1 < results.len() { "," } else { "" };
+        println!(
+            "    {}, \"game\": {}, \"end\": \"{}\"}}{}",
+            json_object(result),
+            game,
+            result.end,
+            separator
+        );
+    }
+    println!("  ],");
+    println!("  \"total\": {}}}", json_object(&total(results)));
+    println!("}}");
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("rstris-sim: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    let mut player = match make_player(&options) {
+        Ok(player) => player,
+        Err(err) => {
+            eprintln!("rstris-sim: {}", err);
+            process::exit(2);
+        }
+    };
+
+    let figures = standard_figures();
+    let results: Vec<GameResult> = (0..options.games)
+        .map(|game| play_game(&mut *player, &figures, &options.sim, options.seed + game))
+        .collect();
+    match options.format {
+        Format::Table => print_table(&results),
+        Format::Csv => print_csv(&results),
+        Format::Json => print_json(&results),
+    }
+}
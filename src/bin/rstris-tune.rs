@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use rstris::ai::linear::Feature;
+use rstris::ai::tuner::*;
+
+static USAGE: &str = "\
+Usage: rstris-tune [options]
+
+Tunes the weights of the linear evaluator by letting a bot play seeded
+games without graphics. The best weights are written as a weights config
+that rstris-sim --eval can load.
+
+Options:
+  --method NAME       cem or ga (cem)
+  --generations N     generations to run in total (10)
+  --population N      candidates per generation (50)
+  --games N           games played by each candidate (4)
+  --pieces N          max pieces per game (500)
+  --features LIST     comma separated features to tune (the Dellacherie
+                      features)
+  --threads N         threads playing games (all cores)
+  --seed N            seed of the run (0)
+  --checkpoint FILE   saved after every generation, and resumed from when
+                      it exists
+  --output FILE       file to write the best weights to (weights.txt)
+  --help              show this text
+";
+
+struct Options {
+    generations: u32,
+    checkpoint: Option<String>,
+    output: String,
+    tuner: TunerConfig,
+}
+
+fn parse_value<T: std::str::FromStr>(name: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", name))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value {} for {}", value, name))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        generations: 10,
+        checkpoint: None,
+        output: "weights.txt".to_owned(),
+        tuner: TunerConfig::default(),
+    };
+    while let Some(arg) = args.next() {
+        let name = arg.as_str();
+        match name {
+            "--method" => {
+                options.tuner.method = match parse_value::<String>(name, args.next())?.as_str() {
+                    "cem" => TuneMethod::cross_entropy(),
+                    "ga" => TuneMethod::genetic(),
+                    other => return Err(format!("unknown method {}", other)),
+                }
+            }
+            "--generations" => options.generations = parse_value(name, args.next())?,
+            "--population" => options.tuner.population = parse_value(name, args.next())?,
+            "--games" => options.tuner.games = parse_value(name, args.next())?,
+            "--pieces" => options.tuner.sim.max_pieces = parse_value(name, args.next())?,
+            "--features" => {
+                let list: String = parse_value(name, args.next())?;
+                options.tuner.features = list
+                    .split(',')
+                    .map(|feature| {
+                        Feature::from_name(feature.trim())
+                            .ok_or_else(|| format!("unknown feature {}", feature))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            "--threads" => options.tuner.threads = parse_value(name, args.next())?,
+            "--seed" => options.tuner.seed = parse_value(name, args.next())?,
+            "--checkpoint" => options.checkpoint = Some(parse_value(name, args.next())?),
+            "--output" => options.output = parse_value(name, args.next())?,
+            "--help" => {
+                print!("{}", USAGE);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown option {}", arg)),
+        }
+    }
+    if options.tuner.population == 0 || options.tuner.games == 0 {
+        return Err("population and games need to be at least 1".to_owned());
+    }
+    Ok(options)
+}
+
+// Write through a temporary file so a checkpoint is never half written
+fn write_file(path: &str, text: &str) -> Result<(), String> {
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, text)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|err| format!("unable to write {}: {}", path, err))
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut tuner = match options.checkpoint {
+        Some(ref path) if Path::new(path).exists() => {
+            let text = fs::read_to_string(path)
+                .map_err(|err| format!("unable to read {}: {}", path, err))?;
+            let tuner = Tuner::from_checkpoint(options.tuner.clone(), &text)
+                .map_err(|err| format!("invalid checkpoint {}: {}", path, err))?;
+            println!(
+                "Resuming from {} at generation {}",
+                path,
+                tuner.generation()
+            );
+            tuner
+        }
+        _ => Tuner::new(options.tuner.clone()),
+    };
+
+    while tuner.generation() < options.generations {
+        let stats = tuner.step();
+        println!(
+            "generation {:>4}: best {:>8.1} mean {:>8.1} (best so far {:.1})",
+            stats.generation,
+            stats.best_fitness,
+            stats.mean_fitness,
+            tuner.best_fitness()
+        );
+        if let Some(ref path) = options.checkpoint {
+            write_file(path, &tuner.checkpoint())?;
+        }
+    }
+
+    let weights = format!(
+        "# Tuned with rstris-tune, mean lines {} at generation {}\n{}",
+        tuner.best_fitness(),
+        tuner.generation(),
+        tuner.best()
+    );
+    write_file(&options.output, &weights)?;
+    println!("Best weights written to {}", options.output);
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("rstris-tune: {}\n\n{}", err, USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(err) = run(options) {
+        eprintln!("rstris-tune: {}", err);
+        process::exit(1);
+    }
+}
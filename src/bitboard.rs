@@ -0,0 +1,366 @@
+use crate::board::Board;
+use crate::figure::{FaceGeometry, Figure};
+use crate::playfield::Playfield;
+use crate::position::Position;
+use crate::vec2::Vec2;
+use crate::zobrist;
+
+//
+// Occupancy only playfield with one bit per block, each row stored as a
+// single word. Collision, drop and full line tests are word operations.
+//
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BitBoard {
+    width: u32,
+    height: u32,
+    // Mask with one bit set for each column of a row
+    full_row: u64,
+    rows: Vec<u64>,
+    // Zobrist hash of the set blocks, kept up to date by every change
+    hash: u64,
+}
+
+// Shift a face row mask to playfield column x
+fn shift_mask(mask: u64, x: i32) -> u64 {
+    if x >= 0 {
+        mask << x
+    } else {
+        mask >> -x
+    }
+}
+
+// Zobrist keys of the blocks of a row mask at row y
+fn row_key(y: usize, mut bits: u64) -> u64 {
+    let mut key = 0;
+    while bits != 0 {
+        let x = bits.trailing_zeros();
+        key ^= zobrist::cell_key((x as i32, y as i32).into());
+        bits &= bits - 1;
+    }
+    key
+}
+
+impl BitBoard {
+    pub fn new(width: u32, height: u32) -> BitBoard {
+        if width > 64 {
+            panic!("bitboard can't be wider than 64 blocks");
+        }
+        BitBoard {
+            width,
+            height,
+            full_row: if width == 64 {
+                u64::MAX
+            } else {
+                (1 << width) - 1
+            },
+            rows: vec![0; height as usize],
+            hash: 0,
+        }
+    }
+    pub fn from_playfield(pf: &Playfield) -> BitBoard {
+        let mut bb = BitBoard::new(pf.width(), pf.height());
+        bb.copy_playfield(pf);
+        bb
+    }
+    pub fn copy_playfield(&mut self, pf: &Playfield) {
+        if self.height != pf.height() || self.width != pf.width() {
+            panic!("can't copy playfield of different sizes");
+        }
+        for (y, row) in pf.blocks().row_iter().enumerate() {
+            self.rows[y] = row
+                .iter()
+                .enumerate()
+                .filter(|(_x, b)| b.is_set())
+                .fold(0, |bits, (x, _b)| bits | 1 << x);
+        }
+        self.hash = pf.zobrist_hash();
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn rows(&self) -> &[u64] {
+        &self.rows
+    }
+    //
+    // Zobrist hash of the blocks, the same as for a playfield with the same
+    // blocks set
+    //
+    pub fn zobrist_hash(&self) -> u64 {
+        self.hash
+    }
+    // Set the bits of row y to bits, updating the hash
+    fn set_row(&mut self, y: usize, bits: u64) {
+        self.hash ^= row_key(y, self.rows[y] ^ bits);
+        self.rows[y] = bits;
+    }
+    pub fn contains(&self, point: Vec2<i32>) -> bool {
+        point.x >= 0 && point.x < self.width as i32 && point.y >= 0 && point.y < self.height as i32
+    }
+    pub fn block_is_set(&self, point: Vec2<i32>) -> bool {
+        if !self.contains(point) {
+            true
+        } else {
+            self.rows[point.y as usize] & (1 << point.x) != 0
+        }
+    }
+    pub fn set_block(&mut self, point: Vec2<i32>) {
+        if self.contains(point) {
+            let y = point.y as usize;
+            self.set_row(y, self.rows[y] | 1 << point.x);
+        }
+    }
+    pub fn clear_block(&mut self, point: Vec2<i32>) {
+        if self.contains(point) {
+            let y = point.y as usize;
+            self.set_row(y, self.rows[y] & !(1 << point.x));
+        }
+    }
+
+    // Test if geometry is (partly) outside of the board at point
+    fn outside(&self, point: Vec2<i32>, geometry: &FaceGeometry) -> bool {
+        point.x + i32::from(geometry.min_x()) < 0
+            || point.x + i32::from(geometry.max_x()) >= self.width as i32
+            || point.y + i32::from(geometry.min_y()) < 0
+            || point.y + i32::from(geometry.max_y()) >= self.height as i32
+    }
+
+    //
+    // Faces (partly) outside of the board are neither placed nor removed
+    //
+    pub fn place(&mut self, point: Vec2<i32>, geometry: &FaceGeometry) {
+        if self.outside(point, geometry) {
+            return;
+        }
+        for (y, mask) in geometry.row_masks().iter().enumerate() {
+            let y = (point.y + y as i32) as usize;
+            self.set_row(y, self.rows[y] | shift_mask(*mask, point.x));
+        }
+    }
+
+    pub fn remove(&mut self, point: Vec2<i32>, geometry: &FaceGeometry) {
+        if self.outside(point, geometry) {
+            return;
+        }
+        for (y, mask) in geometry.row_masks().iter().enumerate() {
+            let y = (point.y + y as i32) as usize;
+            self.set_row(y, self.rows[y] & !shift_mask(*mask, point.x));
+        }
+    }
+
+    pub fn test_collision(&self, point: Vec2<i32>, geometry: &FaceGeometry) -> bool {
+        if self.outside(point, geometry) {
+            return true;
+        }
+        let first_row = geometry.min_y() as usize;
+        let first_pf_row = (point.y + first_row as i32) as usize;
+        geometry.row_masks()[first_row..]
+            .iter()
+            .zip(&self.rows[first_pf_row..])
+            .any(|(mask, row)| row & shift_mask(*mask, point.x) != 0)
+    }
+
+    //
+    // Number of rows the face can fall from point before it lands, the
+    // least number of free rows below any face column. 0 if the face is
+    // outside of the board.
+    //
+    pub fn drop_distance(&self, point: Vec2<i32>, geometry: &FaceGeometry) -> u32 {
+        if self.outside(point, geometry) {
+            return 0;
+        }
+        geometry
+            .iter_bottoms()
+            .map(|(x, y)| {
+                let column = 1 << (point.x + i32::from(x));
+                let below = (point.y + i32::from(y) + 1) as usize;
+                self.rows[below..]
+                    .iter()
+                    .take_while(|row| *row & column == 0)
+                    .count() as u32
+            })
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn full_lines(&self) -> impl Iterator<Item = u32> + '_ {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(move |(_y, row)| **row == self.full_row)
+            .map(|(y, _row)| y as u32)
+    }
+
+    pub fn locked_lines(&self) -> Vec<u32> {
+        self.full_lines().collect()
+    }
+
+    pub fn count_locked_lines(&self) -> u32 {
+        self.rows
+            .iter()
+            .filter(|row| **row == self.full_row)
+            .count() as u32
+    }
+
+    //
+    // Remove a line from the board and move all lines above downwards
+    //
+    pub fn throw_line(&mut self, line: u32) {
+        for y in (0..=line as usize).rev() {
+            let above = if y > 0 { self.rows[y - 1] } else { 0 };
+            self.set_row(y, above);
+        }
+    }
+}
+
+impl Board for BitBoard {
+    fn width(&self) -> u32 {
+        self.width
+    }
+    fn height(&self) -> u32 {
+        self.height
+    }
+    fn occupied(&self, point: Vec2<i32>) -> bool {
+        self.block_is_set(point)
+    }
+    fn set(&mut self, point: Vec2<i32>, _id: u8) {
+        self.set_block(point);
+    }
+    fn clear(&mut self, point: Vec2<i32>) {
+        self.clear_block(point);
+    }
+    fn throw_line(&mut self, line: u32) {
+        BitBoard::throw_line(self, line);
+    }
+    fn copy_from(&mut self, other: &Self) {
+        if self.height != other.height || self.width != other.width {
+            panic!("can't copy bitboard of different sizes");
+        }
+        self.rows.copy_from_slice(&other.rows);
+        self.hash = other.hash;
+    }
+    fn line_is_full(&self, line: u32) -> bool {
+        self.rows[line as usize] == self.full_row
+    }
+    fn locked_lines(&self) -> Vec<u32> {
+        BitBoard::locked_lines(self)
+    }
+    fn count_locked_lines(&self) -> u32 {
+        BitBoard::count_locked_lines(self)
+    }
+    fn place_figure(&mut self, fig: &Figure, position: Position) {
+        self.place(position.xy(), fig.geometry(position.dir()));
+    }
+    fn remove_figure(&mut self, fig: &Figure, position: Position) {
+        self.remove(position.xy(), fig.geometry(position.dir()));
+    }
+    fn test_figure_collision(&self, fig: &Figure, position: Position) -> bool {
+        self.test_collision(position.xy(), fig.geometry(position.dir()))
+    }
+    fn figure_drop_distance(&self, fig: &Figure, position: Position) -> u32 {
+        self.drop_distance(position.xy(), fig.geometry(position.dir()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::figure::{standard_figures, Figure};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    macro_rules! bl {
+        ($x:expr) => {
+            match $x {
+                0 => Block::Clear,
+                _ => Block::Set($x),
+            }
+        };
+    }
+
+    fn figures() -> Vec<Figure> {
+        let standard = standard_figures();
+        vec![
+            standard[0].clone(),
+            standard[4].clone(),
+            standard[6].clone(),
+            // Face columns with gaps
+            Figure::new_from_face(
+                "8",
+                &[&[bl!(8), bl!(8)], &[bl!(8), bl!(0)], &[bl!(8), bl!(8)]],
+            ),
+        ]
+    }
+
+    fn random_pf(rng: &mut StdRng, width: u32, height: u32) -> Playfield {
+        let mut pf = Playfield::new("random", width, height);
+        for y in (height / 2) as i32..height as i32 {
+            let full = rng.gen_range(0, 3) == 0;
+            for x in 0..width as i32 {
+                if full || rng.gen_range(0, 3) != 0 {
+                    pf.set_block((x, y).into(), Block::Set(1));
+                }
+            }
+        }
+        pf
+    }
+
+    #[test]
+    fn same_as_playfield() {
+        let mut rng = StdRng::seed_from_u64(28);
+        for _ in 0..10 {
+            let mut pf = random_pf(&mut rng, 10, 16);
+            let mut bb = BitBoard::from_playfield(&pf);
+            assert_eq!(bb.locked_lines(), pf.locked_lines());
+            assert_eq!(bb.count_locked_lines(), pf.count_locked_lines());
+            for fig in &figures() {
+                for dir in 0..i32::from(fig.num_faces()) {
+                    let geometry = fig.geometry(dir);
+                    for x in -4..12 {
+                        for y in -4..18 {
+                            let pos = (x, y, dir).into();
+                            assert_eq!(
+                                bb.test_collision((x, y).into(), geometry),
+                                fig.test_collision(&pf, pos)
+                            );
+                            if !fig.test_collision(&pf, pos) {
+                                assert_eq!(
+                                    bb.drop_distance((x, y).into(), geometry),
+                                    fig.drop_distance(&pf, pos)
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            for line in pf.locked_lines() {
+                pf.throw_line(line);
+                bb.throw_line(line);
+            }
+            assert_eq!(bb, BitBoard::from_playfield(&pf));
+        }
+    }
+
+    #[test]
+    fn place_and_remove() {
+        let fig = &figures()[0];
+        let mut pf = Playfield::new("pf", 6, 6);
+        let mut bb = BitBoard::new(6, 6);
+        fig.place(&mut pf, (-1, 3, 1).into());
+        bb.place((-1, 3).into(), fig.geometry(1));
+        assert_eq!(bb, BitBoard::from_playfield(&pf));
+        assert_eq!(bb.rows()[4], 0b11);
+        bb.remove((-1, 3).into(), fig.geometry(1));
+        assert_eq!(bb, BitBoard::new(6, 6));
+
+        // Far outside of the board, nothing to shift into the rows
+        for x in [-100, -2, 5, 100] {
+            bb.place((x, 3).into(), fig.geometry(0));
+            assert_eq!(bb, BitBoard::new(6, 6));
+            assert_eq!(bb.drop_distance((x, 0).into(), fig.geometry(0)), 0);
+        }
+    }
+}
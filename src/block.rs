@@ -6,9 +6,6 @@ pub enum Block {
 
 impl Block {
     pub fn is_set(&self) -> bool {
-        match self {
-            Block::Set(_) => true,
-            _ => false,
-        }
+        matches!(self, Block::Set(_))
     }
 }
@@ -0,0 +1,232 @@
+use crate::figure::Figure;
+use crate::position::Position;
+use crate::vec2::Vec2;
+
+//
+// Storage independent view of a playfield. Points outside of the board
+// are always occupied.
+//
+// Only the basic block accessors are required, the figure and line
+// operations have default implementations built on top of them that a
+// board may replace with something faster.
+//
+pub trait Board {
+    fn width(&self) -> u32;
+    fn height(&self) -> u32;
+    fn occupied(&self, point: Vec2<i32>) -> bool;
+    fn set(&mut self, point: Vec2<i32>, id: u8);
+    fn clear(&mut self, point: Vec2<i32>);
+
+    //
+    // Remove a line from the board and move all lines above downwards
+    //
+    fn throw_line(&mut self, line: u32);
+
+    //
+    // Make self a copy of another board of the same size
+    //
+    fn copy_from(&mut self, other: &Self)
+    where
+        Self: Sized;
+
+    fn contains(&self, point: Vec2<i32>) -> bool {
+        point.x >= 0
+            && point.x < self.width() as i32
+            && point.y >= 0
+            && point.y < self.height() as i32
+    }
+
+    fn line_is_full(&self, line: u32) -> bool {
+        (0..self.width() as i32).all(|x| self.occupied((x, line as i32).into()))
+    }
+
+    fn locked_lines(&self) -> Vec<u32> {
+        (0..self.height())
+            .filter(|line| self.line_is_full(*line))
+            .collect()
+    }
+
+    fn count_locked_lines(&self) -> u32 {
+        (0..self.height())
+            .filter(|line| self.line_is_full(*line))
+            .count() as u32
+    }
+
+    //
+    // Test if any block is occupied in column x between rows y_top and
+    // y_bottom (inclusive)
+    //
+    fn test_column_collision(&self, x: i32, y_top: i32, y_bottom: i32) -> bool {
+        (y_top..=y_bottom).any(|y| self.occupied((x, y).into()))
+    }
+
+    //
+    // Count the free rows directly below point before an occupied block or
+    // the bottom of the board is reached
+    //
+    fn free_rows_below(&self, point: Vec2<i32>) -> u32 {
+        let mut free = 0;
+        let mut y = point.y + 1;
+        while !self.occupied((point.x, y).into()) {
+            free += 1;
+            y += 1;
+        }
+        free
+    }
+
+    fn place_figure(&mut self, fig: &Figure, position: Position) {
+        for (x, y, id) in fig.face(position.dir()) {
+            let x = i32::from(*x) + position.x();
+            let y = i32::from(*y) + position.y();
+            self.set((x, y).into(), *id);
+        }
+    }
+
+    fn remove_figure(&mut self, fig: &Figure, position: Position) {
+        for (x, y, _id) in fig.face(position.dir()) {
+            let x = i32::from(*x) + position.x();
+            let y = i32::from(*y) + position.y();
+            self.clear((x, y).into());
+        }
+    }
+
+    fn test_figure_collision(&self, fig: &Figure, position: Position) -> bool {
+        let geometry = fig.geometry(position.dir());
+        if position.x() + i32::from(geometry.min_x()) < 0
+            || position.x() + i32::from(geometry.max_x()) >= self.width() as i32
+            || position.y() + i32::from(geometry.min_y()) < 0
+            || position.y() + i32::from(geometry.max_y()) >= self.height() as i32
+        {
+            // Some part of the face is outside of the board
+            return true;
+        }
+        geometry.iter_columns().any(|(face_x, top, skirt)| {
+            let x = position.x() + i32::from(face_x);
+            if geometry.column_is_solid(face_x) {
+                self.test_column_collision(
+                    x,
+                    position.y() + i32::from(top),
+                    position.y() + i32::from(skirt),
+                )
+            } else {
+                // Column with gaps, only its blocks may collide
+                let mask = geometry.column_masks()[face_x as usize];
+                (top..=skirt)
+                    .filter(|y| mask & (1 << y) != 0)
+                    .any(|y| self.occupied((x, position.y() + i32::from(y)).into()))
+            }
+        })
+    }
+
+    //
+    // Number of rows the figure can fall from position before it lands.
+    // In a face column with gaps every block with a gap below it can land.
+    //
+    fn figure_drop_distance(&self, fig: &Figure, position: Position) -> u32 {
+        fig.geometry(position.dir())
+            .iter_bottoms()
+            .map(|(x, y)| {
+                self.free_rows_below(
+                    (position.x() + i32::from(x), position.y() + i32::from(y)).into(),
+                )
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+    use crate::find_placement::find_placement;
+    use crate::occupancy_grid::OccupancyGrid;
+    use crate::playfield::Playfield;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
+
+    macro_rules! bl {
+        ($x:expr) => {
+            match $x {
+                0 => Block::Clear,
+                _ => Block::Set($x),
+            }
+        };
+    }
+
+    fn figures() -> Vec<Figure> {
+        let standard = standard_figures();
+        vec![
+            standard[1].clone(),
+            standard[3].clone(),
+            standard[6].clone(),
+            // Face columns with gaps
+            Figure::new_from_face(
+                "8",
+                &[&[bl!(8), bl!(8)], &[bl!(8), bl!(0)], &[bl!(8), bl!(8)]],
+            ),
+        ]
+    }
+
+    fn occupancy<B: Board>(board: &B) -> Vec<bool> {
+        let mut blocks = Vec::new();
+        for y in 0..board.height() as i32 {
+            for x in 0..board.width() as i32 {
+                blocks.push(board.occupied((x, y).into()));
+            }
+        }
+        blocks
+    }
+
+    fn drop_and_clear<B: Board>(board: &mut B, fig: &Figure, x: i32, dir: i32) -> Vec<u32> {
+        let start = (x, 0, dir).into();
+        if fig.test_collision(board, start) {
+            return vec![];
+        }
+        let distance = fig.drop_distance(board, start);
+        fig.place(board, (x, distance as i32, dir).into());
+        let lines = board.locked_lines();
+        for line in &lines {
+            board.throw_line(*line);
+        }
+        lines
+    }
+
+    #[test]
+    fn boards_agree() {
+        let mut rng = SmallRng::seed_from_u64(29);
+        let figures = figures();
+        let mut pf = Playfield::new("pf", 4, 12);
+        let mut bb = BitBoard::new(4, 12);
+        let mut grid = OccupancyGrid::new(4, 12);
+        let mut total_lines = 0;
+        for _ in 0..200 {
+            let fig = &figures[rng.gen_range(0, figures.len())];
+            let dir = rng.gen_range(0, i32::from(fig.num_faces()));
+            let x = rng.gen_range(-1, 4);
+
+            let mut placements = (Vec::new(), Vec::new(), Vec::new());
+            find_placement(&mut placements.0, &pf, fig);
+            find_placement(&mut placements.1, &bb, fig);
+            find_placement(&mut placements.2, &grid, fig);
+            assert_eq!(placements.0, placements.1);
+            assert_eq!(placements.0, placements.2);
+
+            let lines = drop_and_clear(&mut pf, fig, x, dir);
+            assert_eq!(lines, drop_and_clear(&mut bb, fig, x, dir));
+            assert_eq!(lines, drop_and_clear(&mut grid, fig, x, dir));
+            total_lines += lines.len();
+
+            assert_eq!(occupancy(&pf), occupancy(&bb));
+            assert_eq!(occupancy(&pf), occupancy(&grid));
+            if pf.occupied((1, 2).into()) {
+                pf = Playfield::new("pf", 4, 12);
+                bb = BitBoard::new(4, 12);
+                grid = OccupancyGrid::new(4, 12);
+            }
+        }
+        assert!(total_lines > 0);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::movement::Movement;
+use crate::position::Position;
+
+//
+// Cost model used by the path search. Costs are accumulated per movement
+// and the estimate is used to guide the search towards the end position.
+//
+pub trait CostModel {
+    //
+    // Cost of a single movement following the previous movement (None for
+    // the first movement of a path)
+    //
+    fn step_cost(&self, previous: Option<Movement>, movement: Movement) -> u64;
+
+    //
+    // Cost of holding left/right until the figure is blocked after moving
+    // the given number of cells. None if holding isn't modelled and every
+    // cell has to be moved separately.
+    //
+    fn hold_cost(&self, cells: u32) -> Option<u64>;
+
+    //
+    // Estimated cost of moving from pos to end_pos. Should not be larger
+    // than the real cost for the search to find the cheapest path.
+    //
+    fn estimate(&self, pos: Position, end_pos: Position, num_faces: u8) -> u64;
+}
+
+// Number of rotations needed to turn from one direction to another
+fn rotation_distance(pos: Position, end_pos: Position, num_faces: u8) -> u64 {
+    let num_faces = i32::from(num_faces.max(1));
+    let diff = (pos.dir() - end_pos.dir()).rem_euclid(num_faces);
+    std::cmp::min(diff, num_faces - diff) as u64
+}
+
+//
+// Every movement costs the same
+//
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MovementCount;
+
+impl CostModel for MovementCount {
+    fn step_cost(&self, _previous: Option<Movement>, _movement: Movement) -> u64 {
+        1
+    }
+    fn hold_cost(&self, _cells: u32) -> Option<u64> {
+        None
+    }
+    fn estimate(&self, pos: Position, end_pos: Position, _num_faces: u8) -> u64 {
+        u64::from(
+            (pos.x() - end_pos.x()).unsigned_abs() + (pos.dir() - end_pos.dir()).unsigned_abs(),
+        )
+    }
+}
+
+//
+// Number of key presses. Moving down is free (the figure is hard dropped
+// or falls by itself) and holding a direction until the figure hits a wall
+// (DAS) is a single press.
+//
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeyPresses;
+
+impl CostModel for KeyPresses {
+    fn step_cost(&self, _previous: Option<Movement>, movement: Movement) -> u64 {
+        match movement {
+            Movement::MoveDown => 0,
+            _ => 1,
+        }
+    }
+    fn hold_cost(&self, _cells: u32) -> Option<u64> {
+        Some(1)
+    }
+    fn estimate(&self, pos: Position, end_pos: Position, num_faces: u8) -> u64 {
+        rotation_distance(pos, end_pos, num_faces)
+    }
+}
+
+//
+// Number of frames needed, given the delayed auto shift (frames from
+// press until the first repeat), auto repeat rate (frames between
+// repeats), gravity (frames per row) and soft drop factor (how many times
+// faster than gravity a soft drop is). Pressing the same key twice in a
+// row needs a frame in between to release it.
+//
+#[derive(Debug, Clone, Copy)]
+pub struct FrameCount {
+    pub das: u64,
+    pub arr: u64,
+    pub gravity: u64,
+    pub soft_drop_factor: u64,
+}
+
+impl CostModel for FrameCount {
+    fn step_cost(&self, previous: Option<Movement>, movement: Movement) -> u64 {
+        match movement {
+            Movement::MoveDown => self.gravity / self.soft_drop_factor.max(1),
+            _ if previous == Some(movement) => 2,
+            _ => 1,
+        }
+    }
+    fn hold_cost(&self, cells: u32) -> Option<u64> {
+        Some(1 + self.das + u64::from(cells.saturating_sub(2)) * self.arr)
+    }
+    fn estimate(&self, pos: Position, end_pos: Position, num_faces: u8) -> u64 {
+        rotation_distance(pos, end_pos, num_faces)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn costs() {
+        let frames = FrameCount {
+            das: 10,
+            arr: 2,
+            gravity: 30,
+            soft_drop_factor: 20,
+        };
+        assert_eq!(frames.step_cost(None, Movement::MoveLeft), 1);
+        assert_eq!(
+            frames.step_cost(Some(Movement::MoveLeft), Movement::MoveLeft),
+            2
+        );
+        assert_eq!(frames.step_cost(None, Movement::MoveDown), 1);
+        assert_eq!(frames.hold_cost(2), Some(11));
+        assert_eq!(frames.hold_cost(5), Some(17));
+        assert_eq!(KeyPresses.hold_cost(5), Some(1));
+        assert_eq!(KeyPresses.step_cost(None, Movement::MoveDown), 0);
+
+        let pos = Position::new((0, 0, 3));
+        let end_pos = Position::new((4, 0, 0));
+        assert_eq!(KeyPresses.estimate(pos, end_pos, 4), 1);
+        assert_eq!(MovementCount.estimate(pos, end_pos, 4), 7);
+    }
+}
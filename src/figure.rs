@@ -1,7 +1,124 @@
 use crate::block::Block;
-use crate::playfield::Playfield;
+use crate::board::Board;
 use crate::position::Position;
 
+//
+// Geometry of a single face, computed once when the figure is built
+//
+#[derive(PartialEq, Clone, Debug)]
+pub struct FaceGeometry {
+    min_x: u8,
+    max_x: u8,
+    min_y: u8,
+    max_y: u8,
+    cells: u8,
+
+    // Lowest (skirt) and highest block row per face column, indexed by
+    // the face local x. Columns without blocks are None.
+    skirt: Vec<Option<u8>>,
+    top: Vec<Option<u8>>,
+
+    // Bit mask of the blocks in each face row, bit n set for x = n
+    row_masks: Vec<u64>,
+    // Bit mask of the blocks in each face column, bit n set for y = n
+    column_masks: Vec<u64>,
+}
+
+impl FaceGeometry {
+    fn from_face(face: &[(u8, u8, u8)]) -> Self {
+        let min_x = face.iter().map(|(x, _y, _id)| *x).min().unwrap_or(0);
+        let max_x = face.iter().map(|(x, _y, _id)| *x).max().unwrap_or(0);
+        let min_y = face.iter().map(|(_x, y, _id)| *y).min().unwrap_or(0);
+        let max_y = face.iter().map(|(_x, y, _id)| *y).max().unwrap_or(0);
+        let mut skirt: Vec<Option<u8>> = vec![None; max_x as usize + 1];
+        let mut top: Vec<Option<u8>> = vec![None; max_x as usize + 1];
+        for (x, y, _id) in face {
+            let col = *x as usize;
+            skirt[col] = Some(skirt[col].map_or(*y, |s| std::cmp::max(s, *y)));
+            top[col] = Some(top[col].map_or(*y, |t| std::cmp::min(t, *y)));
+        }
+        let mut row_masks = vec![0; max_y as usize + 1];
+        let mut column_masks = vec![0; max_x as usize + 1];
+        for (x, y, _id) in face {
+            row_masks[*y as usize] |= 1 << *x;
+            column_masks[*x as usize] |= 1 << *y;
+        }
+        FaceGeometry {
+            min_x,
+            max_x,
+            min_y,
+            max_y,
+            cells: face.len() as u8,
+            skirt,
+            top,
+            row_masks,
+            column_masks,
+        }
+    }
+    pub fn min_x(&self) -> u8 {
+        self.min_x
+    }
+    pub fn max_x(&self) -> u8 {
+        self.max_x
+    }
+    pub fn min_y(&self) -> u8 {
+        self.min_y
+    }
+    pub fn max_y(&self) -> u8 {
+        self.max_y
+    }
+    pub fn width(&self) -> u8 {
+        self.max_x - self.min_x + 1
+    }
+    pub fn height(&self) -> u8 {
+        self.max_y - self.min_y + 1
+    }
+    pub fn cells(&self) -> u8 {
+        self.cells
+    }
+    pub fn skirt(&self) -> &[Option<u8>] {
+        &self.skirt
+    }
+    pub fn top(&self) -> &[Option<u8>] {
+        &self.top
+    }
+    pub fn row_masks(&self) -> &[u64] {
+        &self.row_masks
+    }
+    pub fn column_masks(&self) -> &[u64] {
+        &self.column_masks
+    }
+    // Test if the blocks of a face column are one solid span from top to
+    // skirt
+    pub fn column_is_solid(&self, x: u8) -> bool {
+        let mask = self.column_masks[x as usize];
+        // Without gaps the shifted mask is all ones up to the highest bit
+        let shifted = mask.checked_shr(mask.trailing_zeros()).unwrap_or(0);
+        mask != 0 && shifted & shifted.wrapping_add(1) == 0
+    }
+    // Iterate the blocks with no block of the face directly below them as
+    // (x, y), the blocks a falling figure lands on
+    pub fn iter_bottoms(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.column_masks.iter().enumerate().flat_map(|(x, mask)| {
+            let bottoms = mask & !(mask >> 1);
+            (0..64u8)
+                .filter(move |y| bottoms & (1 << y) != 0)
+                .map(move |y| (x as u8, y))
+        })
+    }
+    // Iterate the columns holding blocks as (x, top, skirt)
+    pub fn iter_columns(&self) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        self.top
+            .iter()
+            .zip(self.skirt.iter())
+            .enumerate()
+            .filter_map(|(x, col)| match col {
+                (Some(top), Some(skirt)) => Some((x as u8, *top, *skirt)),
+                _ => None,
+            })
+    }
+}
+
 #[derive(PartialEq, Clone, Debug)]
 pub struct Figure {
     figure_name: String,
@@ -9,10 +126,20 @@ pub struct Figure {
     // Maximum size of figure in any direction
     max_face_width: u8,
 
+    // Width and height of the face the figure was built from
+    face_size: (u8, u8),
+
     // Each face of the figure is represented as a number of coordinates
     blocks_per_face: u8,
     num_faces: u8,
     faces: Vec<(u8, u8, u8)>,
+    geometry: Vec<FaceGeometry>,
+    shape: Vec<(u8, u8)>,
+}
+
+// Block coordinates of all faces, block ids aren't included
+fn shape(faces: &[(u8, u8, u8)]) -> Vec<(u8, u8)> {
+    faces.iter().map(|(x, y, _id)| (*x, *y)).collect()
 }
 
 impl Figure {
@@ -20,9 +147,12 @@ impl Figure {
         Figure {
             figure_name: name.to_owned(),
             faces: Vec::new(),
+            geometry: Vec::new(),
             blocks_per_face: 0,
             max_face_width: 0,
+            face_size: (0, 0),
             num_faces: 0,
+            shape: Vec::new(),
         }
     }
 
@@ -35,7 +165,6 @@ impl Figure {
             .map(|row| row.iter().filter(|b| b.is_set()).count() as u8)
             .sum();
 
-        println!("Figure {} - blocks_per_face={}", name, blocks_per_face);
         let mut face1 = Vec::new();
         let mut face2 = Vec::new();
         let mut face3 = Vec::new();
@@ -94,45 +223,107 @@ impl Figure {
             .max()
             .unwrap();
 
-        let fig = Figure {
+        let geometry = faces
+            .chunks(blocks_per_face as usize)
+            .map(FaceGeometry::from_face)
+            .collect();
+
+        Figure {
             figure_name: name.to_owned(),
             num_faces: (faces.len() / blocks_per_face as usize) as u8,
+            shape: shape(&faces),
             faces,
+            geometry,
             blocks_per_face,
             max_face_width: max_width,
-        };
-
-        println!(
-            "Built figure {} with {} faces (blocks per face: {}, max width: {})",
-            fig.name(),
-            fig.num_faces(),
-            fig.blocks_per_face,
-            fig.max_face_width
-        );
-        fig
+            face_size: (blocks[0].len() as u8, blocks.len() as u8),
+        }
     }
     pub fn max_width(&self) -> u8 {
         self.max_face_width
     }
 
+    //
+    // Width of the box holding a face. Every other face is rotated 90
+    // degrees from the face the figure was built from. Figures not built
+    // from a face only have the width of the blocks of the face.
+    //
+    pub fn face_box_width(&self, dir: i32) -> u8 {
+        let box_width = if self.face_index(dir) % 2 == 0 {
+            self.face_size.0
+        } else {
+            self.face_size.1
+        };
+        std::cmp::max(box_width, self.geometry(dir).max_x() + 1)
+    }
+
+    //
+    // Build the horizontally mirrored figure named name (S becomes Z, J
+    // becomes L). Face order is kept so that rotating clockwise on the
+    // mirrored figure matches rotating clockwise on the original, i.e.
+    // direction d of the mirror is the mirror of direction -d.
+    //
+    pub fn mirrored(&self, name: &str) -> Figure {
+        let mut faces = Vec::with_capacity(self.faces.len());
+        for dir in 0..i32::from(self.num_faces) {
+            let box_width = self.face_box_width(-dir);
+            let mut face: Vec<(u8, u8, u8)> = self
+                .face(-dir)
+                .iter()
+                .map(|(x, y, id)| (box_width - 1 - *x, *y, *id))
+                .collect();
+            face.sort_by_key(|(x, y, _id)| (*y, *x));
+            faces.extend_from_slice(&face);
+        }
+        let geometry = faces
+            .chunks(self.blocks_per_face.max(1) as usize)
+            .map(FaceGeometry::from_face)
+            .collect();
+        Figure {
+            figure_name: name.to_owned(),
+            max_face_width: self.max_face_width,
+            face_size: self.face_size,
+            blocks_per_face: self.blocks_per_face,
+            num_faces: self.num_faces,
+            shape: shape(&faces),
+            faces,
+            geometry,
+        }
+    }
+
     pub fn name(&self) -> &String {
         &self.figure_name
     }
+    //
+    // Block coordinates of all faces, figures with the same faces in the
+    // same order have the same shape whatever their names and block ids
+    //
+    pub fn shape(&self) -> &[(u8, u8)] {
+        &self.shape
+    }
     pub fn num_faces(&self) -> u8 {
         self.num_faces
     }
 
-    pub fn face(&self, mut dir: i32) -> &[(u8, u8, u8)] {
+    fn face_index(&self, mut dir: i32) -> usize {
         let num_directions = i32::from(self.num_faces());
         while dir < 0 {
             // Handle negative rotation
             dir += num_directions;
         }
         dir %= num_directions;
-        let start_index = dir as usize * self.blocks_per_face as usize;
+        dir as usize
+    }
+
+    pub fn face(&self, dir: i32) -> &[(u8, u8, u8)] {
+        let start_index = self.face_index(dir) * self.blocks_per_face as usize;
         &self.faces[start_index..start_index + self.blocks_per_face as usize]
     }
 
+    pub fn geometry(&self, dir: i32) -> &FaceGeometry {
+        &self.geometry[self.face_index(dir)]
+    }
+
     pub fn iter_faces(&self) -> impl Iterator<Item = &[(u8, u8, u8)]> {
         self.faces.chunks(self.blocks_per_face as usize)
     }
@@ -140,36 +331,89 @@ impl Figure {
     //
     // Place figure in playfield
     //
-    pub fn place(&self, pf: &mut Playfield, position: Position) {
-        pf.place(position.xy(), self.face(position.dir()));
+    pub fn place<B: Board>(&self, pf: &mut B, position: Position) {
+        pf.place_figure(self, position);
     }
     //
     // Remove figure from playfield
     //
-    pub fn remove(&self, pf: &mut Playfield, position: Position) {
-        pf.remove(position.xy(), self.face(position.dir()));
+    pub fn remove<B: Board>(&self, pf: &mut B, position: Position) {
+        pf.remove_figure(self, position);
     }
 
-    pub fn test_collision(&self, pf: &Playfield, position: Position) -> bool {
-        pf.test_collision(position.xy(), self.face(position.dir()))
+    pub fn test_collision<B: Board>(&self, pf: &B, position: Position) -> bool {
+        pf.test_figure_collision(self, position)
     }
 
-    fn row_of_lowest_block(face: &[(u8, u8, u8)]) -> u8 {
-        let mut lowest = 0;
-        for (_x, y, _id) in face.iter() {
-            lowest = std::cmp::max(lowest, *y);
-        }
-        lowest
+    //
+    // Number of rows the figure can fall from position before it lands
+    //
+    pub fn drop_distance<B: Board>(&self, pf: &B, position: Position) -> u32 {
+        pf.figure_drop_distance(self, position)
     }
+
     pub fn lowest_block(&self, dir: i32) -> u8 {
-        Self::row_of_lowest_block(self.face(dir))
+        self.geometry(dir).max_y()
+    }
+}
+
+//
+// The seven standard figures, in the order T, J, L, O, S, Z and I
+//
+pub fn standard_figures() -> Vec<Figure> {
+    macro_rules! bl {
+        ($x:expr) => {
+            match $x {
+                0 => Block::Clear,
+                _ => Block::Set($x),
+            }
+        };
     }
+    vec![
+        Figure::new_from_face(
+            "1",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(1), bl!(1), bl!(1)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        ),
+        Figure::new_from_face(
+            "2",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(2), bl!(2), bl!(2)],
+                &[bl!(0), bl!(0), bl!(2)],
+            ],
+        ),
+        Figure::new_from_face(
+            "3",
+            &[
+                &[bl!(0), bl!(0), bl!(3)],
+                &[bl!(3), bl!(3), bl!(3)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        ),
+        Figure::new_from_face("4", &[&[bl!(4), bl!(4)], &[bl!(4), bl!(4)]]),
+        Figure::new_from_face("5", &[&[bl!(0), bl!(5), bl!(5)], &[bl!(5), bl!(5), bl!(0)]]),
+        Figure::new_from_face("6", &[&[bl!(6), bl!(6), bl!(0)], &[bl!(0), bl!(6), bl!(6)]]),
+        Figure::new_from_face(
+            "7",
+            &[
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+            ],
+        ),
+    ]
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::block::Block;
+    use crate::playfield::Playfield;
 
     macro_rules! bl {
         ($x:expr) => {
@@ -221,4 +465,130 @@ mod tests {
         assert_eq!(fig.face(0), &[(0, 0, 1), (0, 1, 1), (1, 1, 1), (1, 2, 1)]);
         assert_eq!(fig.face(1), &[(0, 0, 1), (1, 0, 1), (1, 1, 1), (2, 1, 1)]);
     }
+    #[test]
+    fn test_geometry() {
+        let fig = Figure::new_from_face(
+            "Figure 1",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(1), bl!(1), bl!(1)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        );
+        let geometry = fig.geometry(0);
+        assert_eq!((geometry.min_x(), geometry.max_x()), (0, 2));
+        assert_eq!((geometry.min_y(), geometry.max_y()), (1, 2));
+        assert_eq!((geometry.width(), geometry.height()), (3, 2));
+        assert_eq!(geometry.cells(), 4);
+        assert_eq!(geometry.skirt(), &[Some(1), Some(2), Some(1)]);
+        assert_eq!(geometry.top(), &[Some(1), Some(1), Some(1)]);
+        assert_eq!(geometry.row_masks(), &[0b000, 0b111, 0b010]);
+
+        let fig = Figure::new_from_face(
+            "Figure 2",
+            &[
+                &[bl!(0), bl!(1), bl!(0)],
+                &[bl!(0), bl!(1), bl!(0)],
+                &[bl!(0), bl!(1), bl!(0)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        );
+        let geometry = fig.geometry(0);
+        assert_eq!((geometry.min_x(), geometry.width()), (1, 1));
+        assert_eq!((geometry.min_y(), geometry.height()), (0, 4));
+        assert_eq!(geometry.skirt(), &[None, Some(3)]);
+        assert_eq!(geometry.iter_columns().collect::<Vec<_>>(), [(1, 0, 3)]);
+        assert_eq!(fig.geometry(-1), fig.geometry(1));
+        assert_eq!(fig.lowest_block(1), 1);
+    }
+    #[test]
+    fn test_collision_and_drop() {
+        let fig = Figure::new_from_face(
+            "Figure 1",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(1), bl!(1), bl!(1)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        );
+        let mut pf = Playfield::new("pf1", 6, 10);
+        assert_eq!(fig.drop_distance(&pf, (0, 0, 0).into()), 7);
+        assert!(!fig.test_collision(&pf, (0, 7, 0).into()));
+        assert!(fig.test_collision(&pf, (0, 8, 0).into()));
+        assert!(fig.test_collision(&pf, (-1, 0, 0).into()));
+        assert!(fig.test_collision(&pf, (4, 0, 0).into()));
+
+        pf.set_block((2, 5).into(), Block::Set(1));
+        assert_eq!(fig.drop_distance(&pf, (0, 0, 0).into()), 3);
+        assert_eq!(fig.drop_distance(&pf, (1, 0, 0).into()), 2);
+        assert!(fig.test_collision(&pf, (1, 3, 0).into()));
+        assert!(!fig.test_collision(&pf, (3, 7, 0).into()));
+    }
+    #[test]
+    fn test_column_with_gap() {
+        // Column 1 has blocks at the top and the bottom only
+        let fig = Figure::new_from_face(
+            "C",
+            &[&[bl!(1), bl!(1)], &[bl!(1), bl!(0)], &[bl!(1), bl!(1)]],
+        );
+        let geometry = fig.geometry(0);
+        assert_eq!(geometry.column_masks(), &[0b111, 0b101]);
+        assert!(geometry.column_is_solid(0));
+        assert!(!geometry.column_is_solid(1));
+        assert_eq!(
+            geometry.iter_bottoms().collect::<Vec<_>>(),
+            [(0, 2), (1, 0), (1, 2)]
+        );
+
+        // A block in the gap doesn't collide, the upper arm lands on it
+        let mut pf = Playfield::new("pf1", 4, 10);
+        pf.set_block((1, 5).into(), Block::Set(1));
+        assert!(!fig.test_collision(&pf, (0, 4, 0).into()));
+        assert!(fig.test_collision(&pf, (0, 3, 0).into()));
+        assert_eq!(fig.drop_distance(&pf, (0, 0, 0).into()), 2);
+        assert_eq!(fig.drop_distance(&pf, (0, 4, 0).into()), 0);
+    }
+    #[test]
+    fn test_mirrored() {
+        let s_fig =
+            Figure::new_from_face("S", &[&[bl!(0), bl!(5), bl!(5)], &[bl!(5), bl!(5), bl!(0)]]);
+        let z_fig =
+            Figure::new_from_face("Z", &[&[bl!(5), bl!(5), bl!(0)], &[bl!(0), bl!(5), bl!(5)]]);
+        assert_eq!(s_fig.mirrored("Z"), z_fig);
+        assert_eq!(z_fig.mirrored("S"), s_fig);
+
+        let l_fig = Figure::new_from_face(
+            "L",
+            &[
+                &[bl!(0), bl!(0), bl!(2)],
+                &[bl!(2), bl!(2), bl!(2)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+        let j_fig = Figure::new_from_face(
+            "J",
+            &[
+                &[bl!(2), bl!(0), bl!(0)],
+                &[bl!(2), bl!(2), bl!(2)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+        assert_eq!(l_fig.mirrored("J"), j_fig);
+        assert_eq!(l_fig.mirrored("J").mirrored("L"), l_fig);
+
+        let i_fig = Figure::new_from_face(
+            "I",
+            &[
+                &[bl!(0), bl!(1), bl!(0)],
+                &[bl!(0), bl!(1), bl!(0)],
+                &[bl!(0), bl!(1), bl!(0)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        );
+        assert_eq!(i_fig.mirrored("I"), i_fig);
+
+        // Without faces there is nothing to mirror
+        let empty = Figure::new("X");
+        assert_eq!(empty.mirrored("Y").num_faces(), 0);
+    }
 }
@@ -1,10 +1,16 @@
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::board::Board;
+use crate::cost_model::{CostModel, MovementCount};
 use crate::figure::Figure;
+use crate::kick_table::KickTable;
 use crate::matrix3::Matrix3;
 use crate::movement::Movement;
-use crate::playfield::Playfield;
 use crate::position::Position;
 
 #[derive(Debug, Clone, Copy)]
@@ -29,8 +35,55 @@ impl PartialEq for NodeIdAndEst {
     }
 }
 
-fn est_pos_distance(start: Position, end: Position) -> u64 {
-    ((start.x() - end.x()).abs() as u64 + (start.dir() - end.dir()).abs() as u64)
+//
+// Timing state of the figure at a node. Kept in integers so that searches
+// give the same paths everywhere.
+//
+#[derive(Clone, Copy, Debug, Default)]
+struct Timing {
+    // Moves made since the figure last fell. Each move adds the rows and
+    // each fallen row subtracts the moves of SearchGravity::MovesPerRow,
+    // moving is allowed while it's not positive.
+    moves: i32,
+    // Moves (or frames, when the figure falls every frame) spent on the
+    // ground since the figure last fell
+    grounded: u32,
+    // Key pressed during the last frame when the figure falls every frame
+    input: Option<Movement>,
+}
+
+impl Timing {
+    // Timing at the start position, with as many moves before the figure
+    // first falls as after every fall
+    fn start(gravity: SearchGravity) -> Timing {
+        match gravity {
+            SearchGravity::MovesPerRow { moves, rows } => Timing {
+                moves: std::cmp::min(0, rows as i32 - moves as i32),
+                ..Timing::default()
+            },
+            _ => Timing::default(),
+        }
+    }
+
+    fn after_moves(&self, cells: u32, rows: u32, grounded: bool) -> Timing {
+        Timing {
+            moves: self.moves + (cells * rows) as i32,
+            grounded: if grounded {
+                self.grounded + cells
+            } else {
+                self.grounded
+            },
+            input: None,
+        }
+    }
+
+    // Test if this timing allows everything the other timing does. A key
+    // pressed during the last frame can't be pressed again right away.
+    fn not_later_than(&self, other: &Timing) -> bool {
+        self.moves <= other.moves
+            && self.grounded <= other.grounded
+            && (self.input.is_none() || self.input == other.input)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -41,7 +94,10 @@ struct Node {
     walked: u64,  // g
     est_end: u64, // h
     movement: Option<Movement>,
-    move_count: f32,
+    timing: Timing,
+    // Next kept node at the same position, and if the node is still kept
+    next_at_pos: Option<usize>,
+    kept: bool,
 }
 
 impl Node {
@@ -52,7 +108,7 @@ impl Node {
         walked: u64,
         est_distance_end: u64,
         movement: Option<Movement>,
-        move_count: f32,
+        timing: Timing,
     ) -> Self {
         Node {
             id,
@@ -61,7 +117,9 @@ impl Node {
             walked,
             est_end: est_distance_end,
             movement,
-            move_count,
+            timing,
+            next_at_pos: None,
+            kept: false,
         }
     }
 
@@ -87,6 +145,11 @@ impl Node {
     }
 }
 
+//
+// Kept nodes by position, as the first node of a list linked through
+// Node::next_at_pos. A position keeps every node that no other node at it
+// is both as cheap and no later than.
+//
 struct NodeByPos {
     pf_width: i32,
     pf_height: i32,
@@ -108,6 +171,16 @@ impl NodeByPos {
         }
     }
 
+    // Get first node at position, positions outside of the matrix have no
+    // node
+    fn get(&self, pos: Position) -> Option<usize> {
+        if self.m.contains(pos.x, pos.y, pos.z) {
+            *self.m.get(pos)
+        } else {
+            None
+        }
+    }
+
     fn resize_and_clear(&mut self, pf_width: i32, pf_height: i32, max_fig_width: i32) {
         if pf_width > self.pf_width
             || pf_height > self.pf_height
@@ -127,10 +200,122 @@ impl NodeByPos {
     }
 }
 
+//
+// A position where the figure can lock together with the cheapest path
+// from the start position
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reachable {
+    pub pos: Position,
+    pub path: Vec<Movement>,
+    // Cost of the path according to the cost model
+    pub cost: u64,
+}
+
+//
+// How the figure falls while it's moved during the search
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchGravity {
+    // A number of movements can be made every time the figure falls a
+    // number of rows
+    MovesPerRow { moves: u32, rows: u32 },
+    // The figure falls a number of rows every frame. A movement takes a
+    // frame, and a held left/right moves the figure again after das frames
+    // and then every arr frames.
+    RowsPerFrame { rows: u32, das: u32, arr: u32 },
+    // The figure falls to the floor after every movement (20G)
+    Instant,
+}
+
+impl SearchGravity {
+    pub fn moves_per_row(moves: u32) -> Self {
+        SearchGravity::MovesPerRow { moves, rows: 1 }
+    }
+}
+
+//
+// Result of a successful search, the path itself is returned separately
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathInfo {
+    // Cost of the path according to the cost model
+    pub cost: u64,
+    // Number of nodes taken from the open set during the search
+    pub expanded_nodes: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchError {
+    // The figure collides already at the start position
+    StartCollides,
+    // Every position reachable from the start was searched without
+    // finding the end position
+    Unreachable,
+    // The node or time budget ran out before the end position was found
+    BudgetExceeded,
+    // The search was cancelled with its cancel token
+    Cancelled,
+}
+
+impl fmt::Display for SearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            SearchError::StartCollides => "figure collides at start position",
+            SearchError::Unreachable => "end position is unreachable",
+            SearchError::BudgetExceeded => "search budget exceeded",
+            SearchError::Cancelled => "search cancelled",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+//
+// Limits on how much work a single search may do. Without limits the
+// search runs until the end is found or every position has been tried.
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SearchBudget {
+    pub max_nodes: Option<usize>,
+    pub max_time: Option<Duration>,
+}
+
+//
+// Token to cancel a running search from another thread. Clones share the
+// same cancelled state.
+//
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, AtomicOrdering::Relaxed);
+    }
+
+    pub fn reset(&self) {
+        self.cancelled.store(false, AtomicOrdering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(AtomicOrdering::Relaxed)
+    }
+}
+
+// How many nodes to expand between checking the clock and cancel token
+const CHECK_INTERVAL: usize = 64;
+
 pub struct FindPath {
     possible_nodes: Vec<usize>,
     end_pos: Position,
-    moves_per_down_step: f32,
+    gravity: SearchGravity,
     fig_faces: u8,
     // Node by id contains all created nodes, indexed by id
     node_by_id: Vec<Node>,
@@ -140,6 +325,15 @@ pub struct FindPath {
     node_by_pos: NodeByPos,
 
     open_set: BinaryHeap<NodeIdAndEst>,
+
+    // Wall kicks per figure shape
+    kick_tables: HashMap<Vec<(u8, u8)>, KickTable>,
+
+    cost_model: Box<dyn CostModel>,
+
+    budget: SearchBudget,
+    cancel_token: Option<CancelToken>,
+    lock_delay: Option<u32>,
 }
 
 impl Default for FindPath {
@@ -155,24 +349,101 @@ impl FindPath {
             possible_nodes: Vec::new(),
             end_pos: Position::new((0, 0, 0)),
             fig_faces: 0,
-            moves_per_down_step: 0.0,
+            gravity: SearchGravity::moves_per_row(1),
             node_by_id: Vec::new(),
             node_by_pos: NodeByPos::new(0, 0, 0),
             open_set: BinaryHeap::new(),
+            kick_tables: HashMap::new(),
+            cost_model: Box::new(MovementCount),
+            budget: SearchBudget::default(),
+            cancel_token: None,
+            lock_delay: None,
+        }
+    }
+
+    //
+    // Set the cost model deciding which path is the best, the default is
+    // to count the number of movements
+    //
+    pub fn set_cost_model(&mut self, cost_model: Box<dyn CostModel>) {
+        self.cost_model = cost_model;
+    }
+
+    //
+    // Use kick table when rotating figures with the shape of fig. A kicked
+    // rotation is a single RotateCW/RotateCCW movement in the found paths.
+    //
+    pub fn set_kick_table(&mut self, fig: &Figure, kick_table: KickTable) {
+        self.kick_tables.insert(fig.shape().to_vec(), kick_table);
+    }
+
+    pub fn clear_kick_tables(&mut self) {
+        self.kick_tables.clear();
+    }
+
+    //
+    // Limit the work done by search
+    //
+    pub fn set_budget(&mut self, budget: SearchBudget) {
+        self.budget = budget;
+    }
+
+    //
+    // Make search stop with SearchError::Cancelled once the token is
+    // cancelled. The token is checked periodically during the search.
+    //
+    pub fn set_cancel_token(&mut self, cancel_token: Option<CancelToken>) {
+        self.cancel_token = cancel_token;
+    }
+
+    //
+    // Let the figure keep moving for lock_delay moves (or frames, when it
+    // falls every frame) after it has landed. Falling a row starts the
+    // delay over. Without lock delay the figure locks when it would have
+    // fallen the next row, or never when it falls every frame.
+    //
+    pub fn set_lock_delay(&mut self, lock_delay: Option<u32>) {
+        self.lock_delay = lock_delay;
+    }
+
+    fn within_lock_delay(&self, timing: &Timing) -> bool {
+        self.lock_delay
+            .is_none_or(|lock_delay| timing.grounded <= lock_delay)
+    }
+
+    // Test if the search has to stop after expanding a number of nodes
+    fn check_limits(&self, expanded_nodes: usize, started: Instant) -> Result<(), SearchError> {
+        if let Some(max_nodes) = self.budget.max_nodes {
+            if expanded_nodes > max_nodes {
+                return Err(SearchError::BudgetExceeded);
+            }
+        }
+        if (expanded_nodes - 1) % CHECK_INTERVAL == 0 {
+            if let Some(ref cancel_token) = self.cancel_token {
+                if cancel_token.is_cancelled() {
+                    return Err(SearchError::Cancelled);
+                }
+            }
+            if let Some(max_time) = self.budget.max_time {
+                if started.elapsed() > max_time {
+                    return Err(SearchError::BudgetExceeded);
+                }
+            }
         }
+        Ok(())
     }
 
     // Prepare the cache for new search
-    fn prepare(
+    fn prepare<B: Board>(
         &mut self,
-        moves_per_down_step: f32,
-        pf: &Playfield,
+        gravity: SearchGravity,
+        pf: &B,
         fig: &Figure,
         end_pos: Position,
     ) {
         self.end_pos = end_pos;
         self.fig_faces = fig.num_faces();
-        self.moves_per_down_step = moves_per_down_step;
+        self.gravity = gravity;
         self.node_by_id.clear();
         self.open_set.clear();
         self.node_by_pos.resize_and_clear(
@@ -181,48 +452,326 @@ impl FindPath {
             i32::from(fig.max_width()),
         );
     }
-    fn new_moved_node(&mut self, node_id: usize, movement: Movement, move_count: f32) {
+    fn new_moved_node(
+        &mut self,
+        node_id: usize,
+        movement: Movement,
+        fig_pos: Position,
+        timing: Timing,
+    ) {
         let node = &self.node_by_id[node_id];
-        let mut fig_pos = Position::apply_move(&node.pos, movement);
-        fig_pos.normalize_dir(self.fig_faces);
+        let cost = self.cost_model.step_cost(node.movement, movement);
+        self.new_node(node_id, movement, fig_pos, node.walked + cost, timing);
+    }
 
-        let distance_to_end = est_pos_distance(fig_pos, self.end_pos);
+    fn new_node(
+        &mut self,
+        parent_id: usize,
+        movement: Movement,
+        fig_pos: Position,
+        walked: u64,
+        timing: Timing,
+    ) {
+        let distance_to_end = self
+            .cost_model
+            .estimate(fig_pos, self.end_pos, self.fig_faces);
         let node_id = self.node_by_id.len();
         let node = Node::new(
             node_id,
-            Some(node.id),
+            Some(parent_id),
             fig_pos,
-            node.walked + 1,
+            walked,
             distance_to_end,
             Some(movement),
-            move_count,
+            timing,
         );
         self.node_by_id.push(node);
         self.possible_nodes.push(node_id);
     }
 
-    fn get_possible_moves(&mut self, node_id: usize) {
+    //
+    // Hold left/right until the figure is blocked
+    //
+    fn new_held_node<B: Board>(
+        &mut self,
+        node_id: usize,
+        movement: Movement,
+        pf: &B,
+        fig: &Figure,
+        rows: u32,
+        grounded: bool,
+    ) {
+        let node = &self.node_by_id[node_id];
+        let (walked, timing) = (node.walked, node.timing);
+        let mut steps = Vec::new();
+        let mut fig_pos = node.pos;
+        while !fig.test_collision(pf, fig_pos.apply_move(movement)) {
+            fig_pos = fig_pos.apply_move(movement);
+            steps.push((movement, fig_pos));
+        }
+        let cells = steps.len() as u32;
+        if cells < 2 {
+            // Same as a single movement
+            return;
+        }
+        let timing = timing.after_moves(cells, rows, grounded);
+        if !self.within_lock_delay(&timing) {
+            return;
+        }
+        if let Some(cost) = self.cost_model.hold_cost(cells) {
+            self.new_chained_node(node_id, &steps, walked + cost, timing);
+        }
+    }
+
+    //
+    // Position after movement. Rotations of figures with a kick table are
+    // resolved through the kicks, None if every kick collides.
+    //
+    fn moved_position<B: Board>(
+        &self,
+        pf: &B,
+        fig: &Figure,
+        pos: Position,
+        movement: Movement,
+    ) -> Option<Position> {
+        if movement == Movement::RotateCW || movement == Movement::RotateCCW {
+            if let Some(kick_table) = self.kick_tables.get(fig.shape()) {
+                return kick_table.rotate(pf, fig, pos, movement);
+            }
+        }
+        let mut fig_pos = pos.apply_move(movement);
+        fig_pos.normalize_dir(self.fig_faces);
+        Some(fig_pos)
+    }
+
+    fn get_possible_moves<B: Board>(&mut self, node_id: usize, pf: &B, fig: &Figure) {
         self.possible_nodes.clear();
-        if self.node_by_id[node_id].move_count <= 0.0 {
-            // We're allowed to move in any direction
-            let new_move_count =
-                self.node_by_id[node_id].move_count + 1.0 / self.moves_per_down_step;
-            for movement in &[
-                Movement::MoveLeft,
-                Movement::MoveRight,
-                Movement::RotateCW,
-                Movement::RotateCCW,
-            ] {
-                self.new_moved_node(node_id, *movement, new_move_count);
+        match self.gravity {
+            SearchGravity::MovesPerRow { moves, rows } => {
+                self.get_possible_row_moves(node_id, pf, fig, moves, rows)
+            }
+            SearchGravity::RowsPerFrame { rows, das, arr } => {
+                self.get_possible_frame_moves(node_id, pf, fig, rows, das, arr)
+            }
+            SearchGravity::Instant => {
+                self.get_possible_frame_moves(node_id, pf, fig, pf.height(), 1, 1)
             }
         }
+    }
+
+    fn get_possible_row_moves<B: Board>(
+        &mut self,
+        node_id: usize,
+        pf: &B,
+        fig: &Figure,
+        moves: u32,
+        rows: u32,
+    ) {
+        let node = &self.node_by_id[node_id];
+        let (pos, timing) = (node.pos, node.timing);
+        let grounded = fig.drop_distance(pf, pos) == 0;
+        let can_move = match self.lock_delay {
+            // The figure doesn't fall any further, only the lock delay
+            // limits the movements
+            Some(_) if grounded => true,
+            _ => timing.moves <= 0,
+        };
+        if can_move {
+            let moved_timing = timing.after_moves(1, rows, grounded);
+            if self.within_lock_delay(&moved_timing) {
+                for movement in &[
+                    Movement::MoveLeft,
+                    Movement::MoveRight,
+                    Movement::RotateCW,
+                    Movement::RotateCCW,
+                ] {
+                    if let Some(fig_pos) = self.moved_position(pf, fig, pos, *movement) {
+                        self.new_moved_node(node_id, *movement, fig_pos, moved_timing);
+                    }
+                }
+            }
+            if self.cost_model.hold_cost(0).is_some() {
+                for movement in &[Movement::MoveLeft, Movement::MoveRight] {
+                    self.new_held_node(node_id, *movement, pf, fig, rows, grounded);
+                }
+            }
+        }
+        let fallen_timing = Timing {
+            moves: timing.moves - moves as i32,
+            ..Timing::default()
+        };
         self.new_moved_node(
             node_id,
             Movement::MoveDown,
-            self.node_by_id[node_id].move_count - 1.0,
+            pos.apply_move(Movement::MoveDown),
+            fallen_timing,
         );
     }
 
+    //
+    // Movements of the figure falling up to rows rows from pos
+    //
+    fn fall<B: Board>(pf: &B, fig: &Figure, pos: Position, rows: u32) -> Vec<(Movement, Position)> {
+        let rows = std::cmp::min(rows, fig.drop_distance(pf, pos)) as i32;
+        (1..=rows)
+            .map(|row| {
+                let fallen = Position::new((pos.x(), pos.y() + row, pos.dir()));
+                (Movement::MoveDown, fallen)
+            })
+            .collect()
+    }
+
+    //
+    // Add the steps as a chain of nodes where only the last one is a
+    // possible move, used when the figure can't stop between the steps
+    //
+    fn new_chained_node(
+        &mut self,
+        parent_id: usize,
+        steps: &[(Movement, Position)],
+        walked: u64,
+        timing: Timing,
+    ) {
+        let (last, steps) = match steps.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+        let parent = &self.node_by_id[parent_id];
+        let (parent_walked, parent_timing) = (parent.walked, parent.timing);
+        let mut parent_id = parent_id;
+        for (movement, fig_pos) in steps {
+            let id = self.node_by_id.len();
+            self.node_by_id.push(Node::new(
+                id,
+                Some(parent_id),
+                *fig_pos,
+                parent_walked,
+                0,
+                Some(*movement),
+                parent_timing,
+            ));
+            parent_id = id;
+        }
+        self.new_node(parent_id, last.0, last.1, walked, timing);
+    }
+
+    //
+    // Possible moves when the figure falls at least a row every frame. Each
+    // move covers the frames from one key press until the next can be made,
+    // including the rows fallen in between.
+    //
+    fn get_possible_frame_moves<B: Board>(
+        &mut self,
+        node_id: usize,
+        pf: &B,
+        fig: &Figure,
+        rows: u32,
+        das: u32,
+        arr: u32,
+    ) {
+        let node = &self.node_by_id[node_id];
+        let (pos, timing, walked) = (node.pos, node.timing, node.walked);
+        let input = timing.input;
+
+        // Let a frame pass without pressing anything
+        let steps = Self::fall(pf, fig, pos, rows);
+        let cost = self.cost_model.step_cost(node.movement, Movement::MoveDown);
+        self.new_chained_node(node_id, &steps, walked + cost, Timing::default());
+
+        for movement in &[
+            Movement::MoveLeft,
+            Movement::MoveRight,
+            Movement::RotateCW,
+            Movement::RotateCCW,
+        ] {
+            let mut steps = Vec::new();
+            let mut frames = 1;
+            if input == Some(*movement) {
+                // The key has to be released for a frame before pressing it
+                // again
+                steps = Self::fall(pf, fig, pos, rows);
+                frames += 1;
+            }
+            let from_pos = steps.last().map_or(pos, |step| step.1);
+            let fig_pos = match self.moved_position(pf, fig, from_pos, *movement) {
+                Some(fig_pos) if !fig.test_collision(pf, fig_pos) => fig_pos,
+                _ => continue,
+            };
+            steps.push((*movement, fig_pos));
+            steps.extend(Self::fall(pf, fig, fig_pos, rows));
+            let moved_timing = Self::frame_timing(timing, &steps, frames, *movement);
+            if self.within_lock_delay(&moved_timing) {
+                let cost = self.cost_model.step_cost(input, *movement);
+                self.new_chained_node(node_id, &steps, walked + cost, moved_timing);
+            }
+        }
+
+        if self.cost_model.hold_cost(0).is_none() {
+            return;
+        }
+        for movement in &[Movement::MoveLeft, Movement::MoveRight] {
+            let mut steps = Vec::new();
+            let mut frames = 1;
+            if input == Some(*movement) {
+                steps = Self::fall(pf, fig, pos, rows);
+                frames += 1;
+            }
+            let mut fig_pos = steps.last().map_or(pos, |step| step.1);
+            let mut cells = 0;
+            loop {
+                // Frames since the last time the figure was moved
+                let wait = match cells {
+                    0 => 0,
+                    1 => das,
+                    _ => arr,
+                };
+                let falls = Self::fall(pf, fig, fig_pos, rows.saturating_mul(wait));
+                let moved = falls
+                    .last()
+                    .map_or(fig_pos, |step| step.1)
+                    .apply_move(*movement);
+                if fig.test_collision(pf, moved) {
+                    break;
+                }
+                steps.extend(falls);
+                steps.push((*movement, moved));
+                fig_pos = moved;
+                frames += wait;
+                cells += 1;
+            }
+            if cells < 2 {
+                // Same as a single movement
+                continue;
+            }
+            steps.extend(Self::fall(pf, fig, fig_pos, rows));
+            let held_timing = Self::frame_timing(timing, &steps, frames, *movement);
+            if !self.within_lock_delay(&held_timing) {
+                continue;
+            }
+            if let Some(cost) = self.cost_model.hold_cost(cells) {
+                self.new_chained_node(node_id, &steps, walked + cost, held_timing);
+            }
+        }
+    }
+
+    //
+    // Timing after steps taking a number of frames, with the key of the
+    // movement pressed during the last frame
+    //
+    fn frame_timing(
+        timing: Timing,
+        steps: &[(Movement, Position)],
+        frames: u32,
+        movement: Movement,
+    ) -> Timing {
+        let fell = steps.iter().any(|step| step.0 == Movement::MoveDown);
+        Timing {
+            moves: 0,
+            grounded: if fell { 0 } else { timing.grounded + frames },
+            input: Some(movement),
+        }
+    }
+
     fn get_node_from_id(&self, id: usize) -> &Node {
         &self.node_by_id[id]
     }
@@ -230,56 +779,838 @@ impl FindPath {
         let best_node = self.open_set.pop().unwrap();
         best_node.id
     }
-    fn no_pos_with_lower_est(&self, node: &Node) -> bool {
-        if let Some(best_node) = *self.node_by_pos.m.get(node.pos) {
-            let n = self.get_node_from_id(best_node);
-            if n.id != node.id && n.get_tot_est() <= node.get_tot_est() {
+
+    //
+    // Test if node can make every move the other node can, at no higher
+    // cost. The time spent on the ground only matters with a lock delay.
+    //
+    fn dominates(&self, node: &Node, other: &Node) -> bool {
+        let mut timing = node.timing;
+        if self.lock_delay.is_none() {
+            timing.grounded = other.timing.grounded;
+        }
+        node.walked <= other.walked && timing.not_later_than(&other.timing)
+    }
+
+    // Test if no kept node at the position of node dominates it
+    fn not_dominated(&self, node: &Node) -> bool {
+        let mut next = self.node_by_pos.get(node.pos);
+        while let Some(id) = next {
+            let n = self.get_node_from_id(id);
+            if n.id != node.id && self.dominates(n, node) {
                 return false;
             }
+            next = n.next_at_pos;
         }
         true
     }
 
-    pub fn search(
+    // Keep a node at its position, dropping the kept nodes it dominates
+    fn keep_node(&mut self, node_id: usize) {
+        let pos = self.node_by_id[node_id].pos;
+        let mut prev: Option<usize> = None;
+        let mut next = self.node_by_pos.get(pos);
+        while let Some(id) = next {
+            next = self.node_by_id[id].next_at_pos;
+            if self.dominates(&self.node_by_id[node_id], &self.node_by_id[id]) {
+                self.node_by_id[id].kept = false;
+                match prev {
+                    Some(prev) => self.node_by_id[prev].next_at_pos = next,
+                    None => self.node_by_pos.m.set(pos, next),
+                }
+            } else {
+                prev = Some(id);
+            }
+        }
+        let node = &mut self.node_by_id[node_id];
+        node.next_at_pos = self.node_by_pos.get(pos);
+        node.kept = true;
+        self.node_by_pos.m.set(pos, Some(node_id));
+    }
+
+    // Cheapest kept node at a position, the first found of equal ones
+    fn cheapest_at(&self, pos: Position) -> Option<usize> {
+        let mut cheapest: Option<&Node> = None;
+        let mut next = self.node_by_pos.get(pos);
+        while let Some(id) = next {
+            let n = self.get_node_from_id(id);
+            if cheapest.is_none_or(|c| (n.walked, n.id) < (c.walked, c.id)) {
+                cheapest = Some(n);
+            }
+            next = n.next_at_pos;
+        }
+        cheapest.map(|n| n.id)
+    }
+
+    //
+    // Search for the cheapest path from start_pos to end_pos. The path is
+    // returned in reverse order, with the last movement first.
+    //
+    pub fn search<B: Board>(
         &mut self,
         path: &mut Vec<Movement>,
-        pf: &Playfield,
+        pf: &B,
         fig: &Figure,
         start_pos: Position,
         end_pos: Position,
-        moves_per_down_step: f32,
-    ) {
-        self.prepare(moves_per_down_step, pf, fig, end_pos);
+        gravity: SearchGravity,
+    ) -> Result<PathInfo, SearchError> {
+        let started = Instant::now();
+        path.clear();
+        if fig.test_collision(pf, start_pos) {
+            return Err(SearchError::StartCollides);
+        }
+        self.prepare(gravity, pf, fig, end_pos);
         let start_node = Node::new(
             self.node_by_id.len(),
             None,
             start_pos,
             0,
-            est_pos_distance(start_pos, end_pos),
+            self.cost_model
+                .estimate(start_pos, end_pos, fig.num_faces()),
             None,
-            0.0,
+            Timing::start(gravity),
         );
 
         self.node_by_id.push(start_node.clone());
         self.open_set.push(start_node.get_id_and_est());
-        self.node_by_pos.m.set(start_node.pos, Some(start_node.id));
-        path.clear();
+        self.keep_node(start_node.id);
 
+        let mut expanded_nodes = 0;
         while !self.open_set.is_empty() {
             let best_node_id = self.pop_best_open();
-            self.get_possible_moves(best_node_id);
-            for node_id in &self.possible_nodes {
+            expanded_nodes += 1;
+            let best_node = &self.node_by_id[best_node_id];
+            if best_node.pos == end_pos {
+                best_node.get_path(path, self);
+                return Ok(PathInfo {
+                    cost: best_node.walked,
+                    expanded_nodes,
+                });
+            }
+            self.check_limits(expanded_nodes, started)?;
+            self.get_possible_moves(best_node_id, pf, fig);
+            let possible_nodes = std::mem::take(&mut self.possible_nodes);
+            for node_id in &possible_nodes {
                 let node = &self.node_by_id[*node_id];
                 let id_and_est = node.get_id_and_est();
-                if node.pos == end_pos {
-                    self.get_node_from_id(*node_id).get_path(path, self);
-                    return;
-                } else if !fig.test_collision(pf, node.pos) && self.no_pos_with_lower_est(node) {
-                    self.node_by_pos.m.set(node.pos, Some(*node_id));
+                if !fig.test_collision(pf, node.pos) && self.not_dominated(node) {
+                    self.keep_node(*node_id);
                     self.open_set.push(id_and_est);
                 }
             }
+            self.possible_nodes = possible_nodes;
         }
-        // No path found
+        Err(SearchError::Unreachable)
+    }
+
+    //
+    // Flood the reachable positions from start_pos and collect every
+    // position where the figure can lock (i.e. can't move further down),
+    // each with the cheapest path leading there. Paths are in the order
+    // the movements are to be executed.
+    //
+    pub fn search_all<B: Board>(
+        &mut self,
+        reachable: &mut Vec<Reachable>,
+        pf: &B,
+        fig: &Figure,
+        mut start_pos: Position,
+        gravity: SearchGravity,
+    ) {
+        reachable.clear();
+        start_pos.normalize_dir(fig.num_faces());
+        if fig.test_collision(pf, start_pos) {
+            return;
+        }
+        self.prepare(gravity, pf, fig, start_pos);
+        let start_node = Node::new(
+            self.node_by_id.len(),
+            None,
+            start_pos,
+            0,
+            0,
+            None,
+            Timing::start(gravity),
+        );
+        self.node_by_id.push(start_node.clone());
+        self.keep_node(start_node.id);
+        self.open_set.push(NodeIdAndEst {
+            id: start_node.id,
+            est: start_node.walked,
+        });
+
+        // Expand nodes in order of walked distance
+        while !self.open_set.is_empty() {
+            let best_node_id = self.pop_best_open();
+            if !self.node_by_id[best_node_id].kept {
+                // A better node has been found for this position
+                continue;
+            }
+            self.get_possible_moves(best_node_id, pf, fig);
+            let possible_nodes = std::mem::take(&mut self.possible_nodes);
+            for node_id in &possible_nodes {
+                let node = &self.node_by_id[*node_id];
+                if !fig.test_collision(pf, node.pos) && self.not_dominated(node) {
+                    let est = node.walked;
+                    self.keep_node(*node_id);
+                    self.open_set.push(NodeIdAndEst { id: *node_id, est });
+                }
+            }
+            self.possible_nodes = possible_nodes;
+        }
+
+        for node in &self.node_by_id {
+            if self.cheapest_at(node.pos) == Some(node.id)
+                && fig.test_collision(pf, node.pos.apply_move(Movement::MoveDown))
+            {
+                let mut path = Vec::new();
+                node.get_path(&mut path, self);
+                path.reverse();
+                reachable.push(Reachable {
+                    pos: node.pos,
+                    path,
+                    cost: node.walked,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::cost_model::{FrameCount, KeyPresses};
+    use crate::find_placement::find_placement;
+    use crate::playfield::Playfield;
+
+    const GRAVITY: SearchGravity = SearchGravity::MovesPerRow { moves: 1, rows: 1 };
+
+    macro_rules! bl {
+        ($x:expr) => {
+            match $x {
+                0 => Block::Clear,
+                _ => Block::Set($x),
+            }
+        };
+    }
+
+    fn fig_t() -> Figure {
+        Figure::new_from_face(
+            "1",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(1), bl!(1), bl!(1)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        )
+    }
+
+    fn replay<B: Board>(pf: &B, fig: &Figure, start_pos: Position, path: &[Movement]) -> Position {
+        let mut pos = start_pos;
+        for movement in path {
+            pos = pos.apply_move(*movement);
+            pos.normalize_dir(fig.num_faces());
+            assert!(!fig.test_collision(pf, pos));
+        }
+        pos
+    }
+
+    #[test]
+    fn search_all_empty() {
+        let fig = fig_t();
+        let pf = Playfield::new("pf", 10, 20);
+        let start_pos = Position::new((4, 0, 0));
+
+        let mut placements = Vec::new();
+        find_placement(&mut placements, &pf, &fig);
+        placements.sort_by_key(|pos| (pos.dir(), pos.x(), pos.y()));
+
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, GRAVITY);
+        for r in &reachable {
+            assert_eq!(replay(&pf, &fig, start_pos, &r.path), r.pos);
+        }
+        let mut landings: Vec<Position> = reachable.iter().map(|r| r.pos).collect();
+        landings.sort_by_key(|pos| (pos.dir(), pos.x(), pos.y()));
+        assert_eq!(landings, placements);
+    }
+
+    #[test]
+    fn search_all_tuck() {
+        let fig = fig_t();
+        let mut pf = Playfield::new("pf", 10, 10);
+        // Overhang at row 7 with a free pocket under it to the left, and a
+        // sealed cavity to the right
+        for x in 0..4 {
+            pf.set_block((x, 7).into(), Block::Set(2));
+        }
+        for x in 6..10 {
+            pf.set_block((x, 7).into(), Block::Set(2));
+        }
+        pf.set_block((6, 8).into(), Block::Set(2));
+        pf.set_block((6, 9).into(), Block::Set(2));
+        let start_pos = Position::new((3, 0, 0));
+
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, GRAVITY);
+
+        // Tucked under the overhang, flat side up
+        let tuck = reachable
+            .iter()
+            .find(|r| r.pos == Position::new((0, 7, 0)))
+            .expect("tuck not found");
+        assert_eq!(replay(&pf, &fig, start_pos, &tuck.path), tuck.pos);
+        assert_eq!(tuck.path.last(), Some(&Movement::MoveLeft));
+
+        // The cavity can only be found by a straight drop scan
+        let mut placements = Vec::new();
+        find_placement(&mut placements, &pf, &fig);
+        let cavity = Position::new((7, 7, 0));
+        assert!(placements.contains(&cavity));
+        assert!(reachable.iter().all(|r| r.pos != cavity));
+    }
+
+    #[test]
+    fn search_errors() {
+        let fig = fig_t();
+        let mut pf = Playfield::new("pf", 10, 20);
+        pf.set_block((0, 19).into(), Block::Set(2));
+        let start_pos = Position::new((4, 0, 0));
+        let end_pos = Position::new((0, 17, 2));
+        let mut path = vec![Movement::MoveUp];
+        let mut find_path = FindPath::new();
+
+        let info = find_path.search(&mut path, &pf, &fig, start_pos, start_pos, GRAVITY);
+        assert_eq!(info.map(|info| info.cost), Ok(0));
+        assert!(path.is_empty());
+
+        let info = find_path
+            .search(&mut path, &pf, &fig, start_pos, end_pos, GRAVITY)
+            .unwrap();
+        assert_eq!(info.cost, path.len() as u64);
+        assert!(info.expanded_nodes > 1);
+
+        let collides = Position::new((-1, 18, 0));
+        let info = find_path.search(&mut path, &pf, &fig, collides, end_pos, GRAVITY);
+        assert_eq!(info, Err(SearchError::StartCollides));
+
+        find_path.set_budget(SearchBudget {
+            max_nodes: Some(10),
+            max_time: None,
+        });
+        let info = find_path.search(&mut path, &pf, &fig, start_pos, end_pos, GRAVITY);
+        assert_eq!(info, Err(SearchError::BudgetExceeded));
+        assert!(path.is_empty());
+        find_path.set_budget(SearchBudget {
+            max_nodes: None,
+            max_time: Some(Duration::from_secs(0)),
+        });
+        let info = find_path.search(&mut path, &pf, &fig, start_pos, end_pos, GRAVITY);
+        assert_eq!(info, Err(SearchError::BudgetExceeded));
+
+        find_path.set_budget(SearchBudget::default());
+        let cancel_token = CancelToken::new();
+        find_path.set_cancel_token(Some(cancel_token.clone()));
+        cancel_token.cancel();
+        let info = find_path.search(&mut path, &pf, &fig, start_pos, end_pos, GRAVITY);
+        assert_eq!(info, Err(SearchError::Cancelled));
+        cancel_token.reset();
+        assert!(find_path
+            .search(&mut path, &pf, &fig, start_pos, end_pos, GRAVITY)
+            .is_ok());
+    }
+
+    // SRS kicks of the JLSTZ figures for the T below, with the y offsets
+    // turned downwards. Direction 0 points down (SRS 2), 1 right (R), 2 up
+    // (0) and 3 left (L).
+    fn srs_t_kicks(fig: &Figure) -> KickTable {
+        let to_r = [(0, 0), (-1, 0), (-1, -1), (0, 2), (-1, 2)];
+        let from_r = [(0, 0), (1, 0), (1, 1), (0, -2), (1, -2)];
+        let to_l = [(0, 0), (1, 0), (1, -1), (0, 2), (1, 2)];
+        let from_l = [(0, 0), (-1, 0), (-1, 1), (0, -2), (-1, -2)];
+        let mut kick_table = KickTable::new(fig);
+        kick_table.set_kicks(2, 1, &to_r);
+        kick_table.set_kicks(0, 1, &to_r);
+        kick_table.set_kicks(1, 2, &from_r);
+        kick_table.set_kicks(1, 0, &from_r);
+        kick_table.set_kicks(2, 3, &to_l);
+        kick_table.set_kicks(0, 3, &to_l);
+        kick_table.set_kicks(3, 2, &from_l);
+        kick_table.set_kicks(3, 0, &from_l);
+        kick_table
+    }
+
+    #[test]
+    fn kicked_rotation() {
+        let fig = Figure::new_from_face(
+            "1",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(1), bl!(1), bl!(1)],
+                &[bl!(0), bl!(1), bl!(0)],
+            ],
+        );
+        // T-spin triple: the slot under the overhang is only reached by
+        // the last kick of rotating from pointing up to pointing right
+        let mut pf = Playfield::new("pf", 6, 8);
+        for (y, row) in ["#.....", "......", ".#####", "..####", ".#####"]
+            .iter()
+            .enumerate()
+        {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    pf.set_block((x as i32, y as i32 + 3).into(), Block::Set(2));
+                }
+            }
+        }
+        let start_pos = Position::new((2, 0, 2));
+        let slot = Position::new((-1, 5, 1));
+
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, GRAVITY);
+        assert!(reachable.iter().all(|r| r.pos != slot));
+
+        let kick_table = srs_t_kicks(&fig);
+        find_path.set_kick_table(&fig, kick_table.clone());
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, GRAVITY);
+        let kicked = reachable
+            .iter()
+            .find(|r| r.pos == slot)
+            .expect("kicked position not found");
+        assert_eq!(kicked.path.last(), Some(&Movement::RotateCCW));
+        assert_eq!(
+            kick_table.rotate(&pf, &fig, (0, 3, 2).into(), Movement::RotateCCW),
+            Some(slot)
+        );
+
+        let mut path = Vec::new();
+        let info = find_path.search(&mut path, &pf, &fig, start_pos, slot, GRAVITY);
+        assert_eq!(info.map(|info| info.cost), Ok(kicked.path.len() as u64));
+        assert!(path.contains(&Movement::RotateCCW));
+        find_path.clear_kick_tables();
+        let info = find_path.search(&mut path, &pf, &fig, start_pos, slot, GRAVITY);
+        assert_eq!(info, Err(SearchError::Unreachable));
+        assert!(path.is_empty());
+        fig.place(&mut pf, slot);
+        assert_eq!(pf.locked_lines(), [5, 6, 7]);
+
+        // A figure with the name of the T but another shape doesn't use
+        // the kicks of the T
+        let mut corridor = Playfield::new("pf", 6, 8);
+        corridor.set_lines(&[1, 4, 7], &Block::Set(2));
+        let other = Figure::new_from_face("1", &[&[bl!(4), bl!(4)], &[bl!(4), bl!(4)]]);
+        let mut kick_table = KickTable::new(&other);
+        kick_table.set_kicks(0, 1, &[(0, 3)]);
+        find_path.set_kick_table(&fig, kick_table);
+        let cavity = Position::new((2, 5, 1));
+        let info = find_path.search(
+            &mut path,
+            &corridor,
+            &other,
+            (2, 2, 0).into(),
+            cavity,
+            GRAVITY,
+        );
+        assert_eq!(info, Err(SearchError::Unreachable));
+    }
+
+    #[test]
+    fn finesse_key_presses() {
+        let fig = fig_t();
+        let pf = Playfield::new("pf", 10, 20);
+        let start_pos = Position::new((4, 0, 0));
+
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.set_cost_model(Box::new(KeyPresses));
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, GRAVITY);
+
+        // Key presses per x for each direction, holding towards a wall is a
+        // single press
+        let expected: [&[(i32, u64)]; 4] = [
+            &[
+                (0, 1),
+                (1, 2),
+                (2, 2),
+                (3, 1),
+                (4, 0),
+                (5, 1),
+                (6, 2),
+                (7, 1),
+            ],
+            &[
+                (-1, 2),
+                (0, 2),
+                (1, 3),
+                (2, 3),
+                (3, 2),
+                (4, 1),
+                (5, 2),
+                (6, 3),
+                (7, 2),
+            ],
+            &[
+                (0, 3),
+                (1, 4),
+                (2, 4),
+                (3, 3),
+                (4, 2),
+                (5, 3),
+                (6, 4),
+                (7, 3),
+            ],
+            &[
+                (0, 2),
+                (1, 3),
+                (2, 3),
+                (3, 2),
+                (4, 1),
+                (5, 2),
+                (6, 3),
+                (7, 2),
+                (8, 2),
+            ],
+        ];
+        for (dir, expected) in expected.iter().enumerate() {
+            let mut costs: Vec<(i32, u64)> = reachable
+                .iter()
+                .filter(|r| r.pos.dir() == dir as i32)
+                .map(|r| (r.pos.x(), r.cost))
+                .collect();
+            costs.sort();
+            assert_eq!(&costs[..], *expected);
+        }
+        for r in &reachable {
+            assert_eq!(replay(&pf, &fig, start_pos, &r.path), r.pos);
+        }
+
+        // Taps are spread out over the fall so that no key has to be released
+        // before pressing it again, which is faster than waiting for the auto
+        // shift
+        find_path.set_cost_model(Box::new(FrameCount {
+            das: 10,
+            arr: 2,
+            gravity: 1,
+            soft_drop_factor: 1,
+        }));
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, GRAVITY);
+        let cost = |x| {
+            reachable
+                .iter()
+                .find(|r| r.pos == Position::new((x, 17, 0)))
+                .map(|r| r.cost)
+                .unwrap()
+        };
+        assert_eq!(cost(3), 1 + 17);
+        assert_eq!(cost(0), 4 + 17);
+    }
+
+    //
+    // Fewest key presses for each column the figure can lock at in
+    // direction dir on an empty board, ordered by the leftmost column.
+    // Directions ending up with the same cells count as the same
+    // placement.
+    //
+    fn presses_per_column(reachable: &[Reachable], fig: &Figure, dir: i32) -> Vec<u64> {
+        let cells = |r: &Reachable| {
+            let mut cells: Vec<(i32, i32)> = fig
+                .face(r.pos.dir())
+                .iter()
+                .map(|(x, y, _)| (r.pos.x() + i32::from(*x), r.pos.y() + i32::from(*y)))
+                .collect();
+            cells.sort();
+            cells
+        };
+        let mut costs: Vec<(i32, u64)> = reachable
+            .iter()
+            .filter(|r| r.pos.dir() == dir)
+            .map(|r| {
+                let placed = cells(r);
+                let cost = reachable
+                    .iter()
+                    .filter(|other| cells(other) == placed)
+                    .map(|other| other.cost)
+                    .min()
+                    .unwrap();
+                (placed[0].0, cost)
+            })
+            .collect();
+        costs.sort();
+        costs.iter().map(|(_, cost)| *cost).collect()
+    }
+
+    #[test]
+    fn guideline_finesse() {
+        // Figures in their spawn orientation, spawning in columns 3-5 (the
+        // I in 3-6 and the O in 4-5)
+        let fig_i = Figure::new_from_face(
+            "7",
+            &[
+                &[bl!(0), bl!(0), bl!(0), bl!(0)],
+                &[bl!(7), bl!(7), bl!(7), bl!(7)],
+                &[bl!(0), bl!(0), bl!(0), bl!(0)],
+                &[bl!(0), bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+        let fig_o = Figure::new_from_face("4", &[&[bl!(4), bl!(4)], &[bl!(4), bl!(4)]]);
+        let fig_s = Figure::new_from_face(
+            "5",
+            &[
+                &[bl!(0), bl!(5), bl!(5)],
+                &[bl!(5), bl!(5), bl!(0)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+        let fig_z = Figure::new_from_face(
+            "6",
+            &[
+                &[bl!(6), bl!(6), bl!(0)],
+                &[bl!(0), bl!(6), bl!(6)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+        let fig_j = Figure::new_from_face(
+            "2",
+            &[
+                &[bl!(2), bl!(0), bl!(0)],
+                &[bl!(2), bl!(2), bl!(2)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+        let fig_l = Figure::new_from_face(
+            "3",
+            &[
+                &[bl!(0), bl!(0), bl!(3)],
+                &[bl!(3), bl!(3), bl!(3)],
+                &[bl!(0), bl!(0), bl!(0)],
+            ],
+        );
+
+        // Key presses per leftmost column, from the finesse table of the
+        // guideline. Holding towards a wall is a single press.
+        let flat_3: &[u64] = &[1, 2, 1, 0, 1, 2, 2, 1];
+        let flipped_3: &[u64] = &[3, 4, 3, 2, 3, 4, 4, 3];
+        let left_3: &[u64] = &[2, 3, 2, 1, 2, 3, 3, 2, 2];
+        let right_3: &[u64] = &[2, 2, 3, 2, 1, 2, 3, 3, 2];
+        let upright_sz: &[u64] = &[2, 2, 2, 1, 1, 2, 3, 2, 2];
+        let flat_i: &[u64] = &[1, 2, 1, 0, 1, 2, 1];
+        let upright_i: &[u64] = &[2, 2, 2, 2, 1, 1, 2, 2, 2, 2];
+        let square: &[u64] = &[1, 2, 2, 1, 0, 1, 2, 2, 1];
+        let cases: [(&Figure, i32, [&[u64]; 4]); 6] = [
+            (&fig_i, 3, [flat_i, upright_i, flat_i, upright_i]),
+            // The O keeps a second, identical, direction
+            (&fig_o, 4, [square, square, &[], &[]]),
+            (&fig_s, 3, [flat_3, upright_sz, flat_3, upright_sz]),
+            (&fig_z, 3, [flat_3, upright_sz, flat_3, upright_sz]),
+            // Direction 1 takes up the left two columns of the 3x3 box
+            // and direction 3 the right two
+            (&fig_j, 3, [flat_3, left_3, flipped_3, right_3]),
+            (&fig_l, 3, [flat_3, left_3, flipped_3, right_3]),
+        ];
+
+        let pf = Playfield::new("pf", 10, 20);
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.set_cost_model(Box::new(KeyPresses));
+        for (fig, x, expected) in cases.iter() {
+            let start_pos = Position::new((*x, 0, 0));
+            find_path.search_all(&mut reachable, &pf, fig, start_pos, GRAVITY);
+            for (dir, expected) in expected.iter().enumerate() {
+                assert_eq!(
+                    presses_per_column(&reachable, fig, dir as i32),
+                    *expected,
+                    "figure {} direction {}",
+                    fig.name(),
+                    dir
+                );
+            }
+            for r in &reachable {
+                assert_eq!(replay(&pf, fig, start_pos, &r.path), r.pos);
+            }
+        }
+    }
+
+    #[test]
+    fn instant_gravity() {
+        let fig = fig_t();
+        let mut pf = Playfield::new("pf", 10, 12);
+        // Tower two rows high the figure has to climb over
+        pf.set_block((6, 10).into(), Block::Set(2));
+        pf.set_block((6, 11).into(), Block::Set(2));
+        let start_pos = Position::new((1, 0, 0));
+
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, SearchGravity::Instant);
+        assert!(!reachable.is_empty());
+        assert!(reachable.iter().all(|r| r.pos.x() < 6));
+
+        // Falling a row per frame leaves time to pass over the tower
+        let gravity = SearchGravity::RowsPerFrame {
+            rows: 1,
+            das: 10,
+            arr: 2,
+        };
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, gravity);
+        assert!(reachable.iter().any(|r| r.pos.x() > 6));
+        for r in &reachable {
+            assert_eq!(replay(&pf, &fig, start_pos, &r.path), r.pos);
+        }
+
+        // Climb by kicking upwards when rotating
+        let mut kick_table = KickTable::new(&fig);
+        for dir in 0..4 {
+            kick_table.set_kicks(dir, dir + 1, &[(0, 0), (0, -1), (0, -2)]);
+            kick_table.set_kicks(dir, dir - 1, &[(0, 0), (0, -1), (0, -2)]);
+        }
+        find_path.set_kick_table(&fig, kick_table.clone());
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, SearchGravity::Instant);
+        assert!(reachable.iter().any(|r| r.pos.x() > 6));
+
+        // Every path works when the figure falls to the floor after every
+        // movement
+        for r in &reachable {
+            let mut pos = start_pos;
+            for movement in &r.path {
+                if *movement != Movement::MoveDown {
+                    pos = match movement {
+                        Movement::RotateCW | Movement::RotateCCW => {
+                            kick_table.rotate(&pf, &fig, pos, *movement).unwrap()
+                        }
+                        _ => pos.apply_move(*movement),
+                    };
+                    assert!(!fig.test_collision(&pf, pos));
+                }
+                pos = Position::new((
+                    pos.x(),
+                    pos.y() + fig.drop_distance(&pf, pos) as i32,
+                    pos.dir(),
+                ));
+            }
+            assert_eq!(pos, r.pos);
+        }
+    }
+
+    #[test]
+    fn timing_held_key() {
+        let released = Timing::default();
+        let held = Timing {
+            input: Some(Movement::MoveLeft),
+            ..Timing::default()
+        };
+        let other_held = Timing {
+            input: Some(Movement::MoveRight),
+            ..Timing::default()
+        };
+        // A held key has to be released before it can be pressed again
+        assert!(released.not_later_than(&held));
+        assert!(!held.not_later_than(&released));
+        assert!(!held.not_later_than(&other_held));
+        assert!(held.not_later_than(&held));
+    }
+
+    #[test]
+    fn lock_delay() {
+        let fig = fig_t();
+        let pf = Playfield::new("pf", 10, 12);
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        let mut flat_xs = |find_path: &mut FindPath, start_pos, gravity| {
+            find_path.search_all(&mut reachable, &pf, &fig, start_pos, gravity);
+            let mut xs: Vec<i32> = reachable
+                .iter()
+                .filter(|r| r.pos.dir() == 0)
+                .map(|r| r.pos.x())
+                .collect();
+            xs.sort();
+            xs
+        };
+
+        // At 20G the figure lands after the first movement, and the same
+        // key has to be released for a frame before moving again
+        let start_pos = Position::new((4, 0, 0));
+        let gravity = SearchGravity::Instant;
+        assert_eq!(
+            flat_xs(&mut find_path, start_pos, gravity),
+            (0..8).collect::<Vec<_>>()
+        );
+        find_path.set_lock_delay(Some(0));
+        assert_eq!(flat_xs(&mut find_path, start_pos, gravity), [3, 4, 5]);
+        find_path.set_lock_delay(Some(3));
+        assert_eq!(flat_xs(&mut find_path, start_pos, gravity), [2, 3, 4, 5, 6]);
+
+        // Already on the floor, without lock delay there's one movement
+        // left before the figure would have fallen
+        let start_pos = Position::new((4, 9, 0));
+        let gravity = SearchGravity::moves_per_row(1);
+        find_path.set_lock_delay(None);
+        assert_eq!(flat_xs(&mut find_path, start_pos, gravity), [3, 4, 5]);
+        find_path.set_lock_delay(Some(2));
+        assert_eq!(flat_xs(&mut find_path, start_pos, gravity), [2, 3, 4, 5, 6]);
+
+        // Two movements per row, also before the figure first falls
+        find_path.set_lock_delay(None);
+        let gravity = SearchGravity::moves_per_row(2);
+        assert_eq!(flat_xs(&mut find_path, start_pos, gravity), [2, 3, 4, 5, 6]);
+
+        // Two rows between each movement
+        let start_pos = Position::new((4, 0, 0));
+        let gravity = SearchGravity::MovesPerRow { moves: 1, rows: 2 };
+        find_path.set_lock_delay(None);
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, gravity);
+        for r in &reachable {
+            let mut rows = 0;
+            let mut moves = 0;
+            for movement in &r.path {
+                if *movement == Movement::MoveDown {
+                    rows += 1;
+                } else {
+                    moves += 1;
+                    assert!((moves - 1) * 2 <= rows);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lock_delay_tapped_tuck() {
+        let fig = fig_t();
+        let mut pf = Playfield::new("pf", 6, 5);
+        // Only leaves room to turn upright against the left wall
+        pf.set_block((2, 2).into(), Block::Set(2));
+        let start_pos = Position::new((3, 2, 0));
+        let tuck = Position::new((0, 2, 1));
+        let gravity = SearchGravity::RowsPerFrame {
+            rows: 1,
+            das: 10,
+            arr: 1,
+        };
+
+        // Holding the key to the wall is one press but takes twelve
+        // frames, tapping it three times takes five, so only the tapped
+        // figure has time left to turn
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.set_cost_model(Box::new(KeyPresses));
+        find_path.set_lock_delay(Some(12));
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, gravity);
+        let r = reachable.iter().find(|r| r.pos == tuck).unwrap();
+        assert_eq!(replay(&pf, &fig, start_pos, &r.path), tuck);
+        let mut path = Vec::new();
+        find_path
+            .search(&mut path, &pf, &fig, start_pos, tuck, gravity)
+            .unwrap();
+        // The path is returned last movement first
+        path.reverse();
+        assert_eq!(replay(&pf, &fig, start_pos, &path), tuck);
+
+        find_path.set_lock_delay(Some(5));
+        find_path.search_all(&mut reachable, &pf, &fig, start_pos, gravity);
+        assert!(reachable.iter().all(|r| r.pos != tuck));
     }
 }
@@ -1,31 +1,97 @@
+use std::collections::HashMap;
+
+use crate::board::Board;
 use crate::figure::Figure;
-use crate::playfield::*;
 use crate::position::Position;
 
-pub fn find_placement(placements: &mut Vec<Position>, pf: &Playfield, fig: &Figure) {
-    for (dir, face) in fig.iter_faces().enumerate() {
-        for x in -(i32::from(fig.max_width() / 2))..pf.width() as i32 {
+//
+// Placement identified by the cells the figure locks, together with all
+// positions locking exactly those cells
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct UniquePlacement {
+    // Board cells (x, y) in sorted order
+    pub cells: Vec<(i32, i32)>,
+    pub positions: Vec<Position>,
+}
+
+//
+// Find every position where the figure can lock, i.e. where it doesn't
+// collide but would collide if moved down. Positions are scanned for each
+// face only where all of its blocks are within the board. Placements are
+// appended to placements.
+//
+pub fn find_placement<B: Board>(placements: &mut Vec<Position>, pf: &B, fig: &Figure) {
+    for dir in 0..i32::from(fig.num_faces()) {
+        let geometry = fig.geometry(dir);
+        let min_x = -i32::from(geometry.min_x());
+        let max_x = pf.width() as i32 - 1 - i32::from(geometry.max_x());
+        let min_y = -i32::from(geometry.min_y());
+        let max_y = pf.height() as i32 - 1 - i32::from(geometry.max_y());
+        for x in min_x..=max_x {
             let mut last_pos = None;
-            for y in 0..pf.height() {
-                let pos = (x as i32, y as i32).into();
-                if pf.test_collision(pos, face) {
+            // One row past the lowest position to end at the floor
+            for y in min_y..=max_y + 1 {
+                let pos = (x, y, dir).into();
+                if fig.test_collision(pf, pos) {
                     if let Some(p) = last_pos {
                         placements.push(p);
                     }
                     last_pos = None;
                 } else {
-                    last_pos = Some((x as i32, y as i32, dir as i32).into());
+                    last_pos = Some(pos);
                 }
             }
         }
     }
 }
 
+//
+// Find placements like find_placement, but merge the positions where the
+// figure locks the same cells (symmetric faces) into one placement. Like
+// find_placement the placements are appended, only the appended ones are
+// merged.
+//
+pub fn find_unique_placement<B: Board>(
+    placements: &mut Vec<UniquePlacement>,
+    pf: &B,
+    fig: &Figure,
+) {
+    let mut positions = Vec::new();
+    find_placement(&mut positions, pf, fig);
+
+    let mut index_by_cells = HashMap::new();
+    for pos in positions {
+        let mut cells: Vec<(i32, i32)> = fig
+            .face(pos.dir())
+            .iter()
+            .map(|(x, y, _id)| (pos.x() + i32::from(*x), pos.y() + i32::from(*y)))
+            .collect();
+        cells.sort();
+        match index_by_cells.get(&cells) {
+            Some(index) => {
+                let placement: &mut UniquePlacement = &mut placements[*index];
+                placement.positions.push(pos);
+            }
+            None => {
+                index_by_cells.insert(cells.clone(), placements.len());
+                placements.push(UniquePlacement {
+                    cells,
+                    positions: vec![pos],
+                });
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::block::Block;
     use crate::figure::*;
+    use crate::playfield::Playfield;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
 
     macro_rules! bl {
         ($x:expr) => {
@@ -75,6 +141,156 @@ mod tests {
         find_placement(&mut placements, &pf, &fig);
         assert_eq!(placements.len(), 74);
     }
+    fn fig_s() -> Figure {
+        Figure::new_from_face("5", &[&[bl!(0), bl!(5), bl!(5)], &[bl!(5), bl!(5), bl!(0)]])
+    }
+
+    fn fig_i() -> Figure {
+        Figure::new_from_face(
+            "7",
+            &[
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+            ],
+        )
+    }
+
+    fn random_pf(rng: &mut StdRng, width: u32, height: u32) -> Playfield {
+        let mut pf = Playfield::new("random", width, height);
+        for x in 0..width as i32 {
+            let col_height = rng.gen_range(0, height as i32 / 2);
+            for y in (height as i32 - col_height)..height as i32 {
+                if rng.gen_range(0, 4) != 0 {
+                    pf.set_block((x, y).into(), Block::Set(1));
+                }
+            }
+        }
+        pf
+    }
+
+    #[test]
+    fn find_mirrored() {
+        let mut rng = StdRng::seed_from_u64(27);
+        for fig in &[fig2(), fig3(), fig_s(), fig_i()] {
+            let mirrored_fig = fig.mirrored(&format!("mirrored {}", fig.name()));
+            for _ in 0..20 {
+                let pf = random_pf(&mut rng, 10, 20);
+                let mirrored_pf = pf.mirrored();
+
+                let mut placements = Vec::new();
+                find_placement(&mut placements, &pf, fig);
+                let mut expected: Vec<_> = placements
+                    .iter()
+                    .map(|pos| pos.mirrored(fig, pf.width()))
+                    .map(|pos| (pos.x(), pos.y(), pos.dir()))
+                    .collect();
+                expected.sort();
+
+                let mut mirrored_placements = Vec::new();
+                find_placement(&mut mirrored_placements, &mirrored_pf, &mirrored_fig);
+                let mut mirrored: Vec<_> = mirrored_placements
+                    .iter()
+                    .map(|pos| (pos.x(), pos.y(), pos.dir()))
+                    .collect();
+                mirrored.sort();
+
+                assert_eq!(expected, mirrored);
+            }
+        }
+    }
+
+    #[test]
+    fn find_unique() {
+        let pf = Playfield::new("pf1", 10, 20);
+        let fig_o = Figure::new_from_face("4", &[&[bl!(4), bl!(4)], &[bl!(4), bl!(4)]]);
+        let mut placements = Vec::new();
+        let mut unique = Vec::new();
+        for (fig, expected) in &[(fig_o, 9), (fig_i(), 17), (fig_s(), 17), (fig2(), 34)] {
+            placements.clear();
+            unique.clear();
+            find_placement(&mut placements, &pf, fig);
+            find_unique_placement(&mut unique, &pf, fig);
+            assert_eq!(unique.len(), *expected);
+            assert_eq!(
+                unique.iter().map(|p| p.positions.len()).sum::<usize>(),
+                placements.len()
+            );
+            for placement in &unique {
+                for pos in &placement.positions {
+                    let mut cells: Vec<_> = fig
+                        .face(pos.dir())
+                        .iter()
+                        .map(|(x, y, _)| (pos.x() + i32::from(*x), pos.y() + i32::from(*y)))
+                        .collect();
+                    cells.sort();
+                    assert_eq!(cells, placement.cells);
+                }
+            }
+        }
+
+        // Both append, and only merge the appended placements
+        find_placement(&mut placements, &pf, &fig2());
+        assert_eq!(placements.len(), 68);
+        find_unique_placement(&mut unique, &pf, &fig2());
+        assert_eq!(unique.len(), 68);
+    }
+
+    // Test every position, also far outside of the board
+    fn brute_force_placement(pf: &Playfield, fig: &Figure) -> Vec<(i32, i32, i32)> {
+        let mut placements = Vec::new();
+        for dir in 0..i32::from(fig.num_faces()) {
+            for x in -8..pf.width() as i32 + 8 {
+                for y in -8..pf.height() as i32 + 8 {
+                    let pos = Position::new((x, y, dir));
+                    if !fig.test_collision(pf, pos)
+                        && fig.test_collision(pf, Position::new((x, y + 1, dir)))
+                    {
+                        placements.push((x, y, dir));
+                    }
+                }
+            }
+        }
+        placements
+    }
+
+    #[test]
+    fn find_brute_force() {
+        let mut rng = StdRng::seed_from_u64(40);
+        let fig_wide_i = Figure::new_from_face(
+            "7",
+            &[
+                &[bl!(0), bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(0), bl!(7), bl!(0)],
+            ],
+        );
+        let fig_dot = Figure::new_from_face(
+            "8",
+            &[
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(0), bl!(0), bl!(0)],
+                &[bl!(0), bl!(0), bl!(8)],
+            ],
+        );
+        for fig in &[fig2(), fig3(), fig_s(), fig_i(), fig_wide_i, fig_dot] {
+            for _ in 0..20 {
+                let pf = random_pf(&mut rng, 8, 12);
+                let mut placements = Vec::new();
+                find_placement(&mut placements, &pf, fig);
+                let mut found: Vec<_> = placements
+                    .iter()
+                    .map(|pos| (pos.x(), pos.y(), pos.dir()))
+                    .collect();
+                found.sort();
+                let mut expected = brute_force_placement(&pf, fig);
+                expected.sort();
+                assert_eq!(found, expected);
+            }
+        }
+    }
     /*
     use test::Bencher;
     #[bench]
@@ -0,0 +1,342 @@
+use crate::board::Board;
+use crate::cost_model::{CostModel, KeyPresses};
+use crate::figure::Figure;
+use crate::find_path::{FindPath, Reachable, SearchGravity};
+use crate::input_script::InputEvent;
+use crate::kick_table::KickTable;
+use crate::movement::Movement;
+use crate::position::Position;
+
+//
+// Finesse of a single placed figure, comparing the key presses the player
+// used with the fewest possible to reach the same position
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinesseResult {
+    pub pos: Position,
+    pub presses: u64,
+    pub optimal_presses: u64,
+    pub optimal_path: Vec<Movement>,
+}
+
+impl FinesseResult {
+    pub fn faults(&self) -> u64 {
+        self.presses.saturating_sub(self.optimal_presses)
+    }
+}
+
+//
+// Finesse statistics accumulated over all analyzed figures
+//
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FinesseStats {
+    pub pieces: u32,
+    pub faulted_pieces: u32,
+    pub presses: u64,
+    pub optimal_presses: u64,
+}
+
+impl FinesseStats {
+    fn add(&mut self, result: &FinesseResult) {
+        self.pieces += 1;
+        if result.faults() > 0 {
+            self.faulted_pieces += 1;
+        }
+        self.presses += result.presses;
+        self.optimal_presses += result.optimal_presses;
+    }
+
+    pub fn faults(&self) -> u64 {
+        self.presses.saturating_sub(self.optimal_presses)
+    }
+
+    pub fn faults_per_piece(&self) -> f32 {
+        if self.pieces == 0 {
+            return 0.0;
+        }
+        self.faults() as f32 / self.pieces as f32
+    }
+
+    // Share of the figures placed without faults
+    pub fn accuracy(&self) -> f32 {
+        if self.pieces == 0 {
+            return 1.0;
+        }
+        (self.pieces - self.faulted_pieces) as f32 / self.pieces as f32
+    }
+}
+
+//
+// Finds finesse faults in the input used by a player. Input can either be
+// given afterwards from a recorded log with analyze or analyze_script, or
+// be recorded while playing with start_piece, record_move, record_event
+// and finish_piece.
+//
+// Every movement is a separate tap, also when it's blocked. Only a key
+// press of an input script can be held, auto shifting any number of
+// times for a single press. Soft drop isn't counted.
+//
+pub struct FinesseAnalyzer {
+    find_path: FindPath,
+    reachable: Vec<Reachable>,
+    stats: FinesseStats,
+
+    // Figure being recorded, its start position and presses so far
+    current: Option<(Figure, Position, u64)>,
+}
+
+impl Default for FinesseAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FinesseAnalyzer {
+    pub fn new() -> Self {
+        let mut find_path = FindPath::new();
+        find_path.set_cost_model(Box::new(KeyPresses));
+        FinesseAnalyzer {
+            find_path,
+            reachable: Vec::new(),
+            stats: FinesseStats::default(),
+            current: None,
+        }
+    }
+
+    pub fn set_kick_table(&mut self, fig: &Figure, kick_table: KickTable) {
+        self.find_path.set_kick_table(fig, kick_table);
+    }
+
+    pub fn stats(&self) -> &FinesseStats {
+        &self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = FinesseStats::default();
+    }
+
+    //
+    // Analyze the movements used to move figure from start_pos to the
+    // landed end_pos, each movement tapped. Returns None if end_pos can't
+    // be reached.
+    //
+    pub fn analyze<B: Board>(
+        &mut self,
+        pf: &B,
+        fig: &Figure,
+        start_pos: Position,
+        movements: &[Movement],
+        end_pos: Position,
+        gravity: SearchGravity,
+    ) -> Option<FinesseResult> {
+        let presses = movements.iter().map(|m| press_cost(*m)).sum();
+        self.analyze_presses(pf, fig, start_pos, presses, end_pos, gravity)
+    }
+
+    //
+    // Analyze the key events of an input script moving figure from
+    // start_pos to the landed end_pos. Returns None if end_pos can't be
+    // reached.
+    //
+    pub fn analyze_script<B: Board>(
+        &mut self,
+        pf: &B,
+        fig: &Figure,
+        start_pos: Position,
+        script: &[InputEvent],
+        end_pos: Position,
+        gravity: SearchGravity,
+    ) -> Option<FinesseResult> {
+        let presses = script.iter().map(event_cost).sum();
+        self.analyze_presses(pf, fig, start_pos, presses, end_pos, gravity)
+    }
+
+    fn analyze_presses<B: Board>(
+        &mut self,
+        pf: &B,
+        fig: &Figure,
+        start_pos: Position,
+        presses: u64,
+        end_pos: Position,
+        gravity: SearchGravity,
+    ) -> Option<FinesseResult> {
+        self.find_path
+            .search_all(&mut self.reachable, pf, fig, start_pos, gravity);
+        let optimal = self.reachable.iter().find(|r| r.pos == end_pos)?;
+        let result = FinesseResult {
+            pos: end_pos,
+            presses,
+            optimal_presses: optimal.cost,
+            optimal_path: optimal.path.clone(),
+        };
+        self.stats.add(&result);
+        Some(result)
+    }
+
+    //
+    // Start recording the input of a new figure
+    //
+    pub fn start_piece(&mut self, fig: &Figure, start_pos: Position) {
+        self.current = Some((fig.clone(), start_pos, 0));
+    }
+
+    // Record a tapped movement
+    pub fn record_move(&mut self, movement: Movement) {
+        if let Some((_, _, ref mut presses)) = self.current {
+            *presses += press_cost(movement);
+        }
+    }
+
+    // Record a key event, a held key is a single press
+    pub fn record_event(&mut self, event: &InputEvent) {
+        if let Some((_, _, ref mut presses)) = self.current {
+            *presses += event_cost(event);
+        }
+    }
+
+    //
+    // Analyze the recorded figure once it has landed at end_pos
+    //
+    pub fn finish_piece<B: Board>(
+        &mut self,
+        pf: &B,
+        end_pos: Position,
+        gravity: SearchGravity,
+    ) -> Option<FinesseResult> {
+        let (fig, start_pos, presses) = self.current.take()?;
+        self.analyze_presses(pf, &fig, start_pos, presses, end_pos, gravity)
+    }
+}
+
+// Key presses of a tapped movement, counted as the search does
+fn press_cost(movement: Movement) -> u64 {
+    KeyPresses.step_cost(None, movement)
+}
+
+fn event_cost(event: &InputEvent) -> u64 {
+    if event.pressed {
+        press_cost(event.key)
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::figure::standard_figures;
+    use crate::playfield::Playfield;
+
+    const GRAVITY: SearchGravity = SearchGravity::MovesPerRow { moves: 1, rows: 1 };
+
+    fn with_drop(movements: &[Movement], rows: usize) -> Vec<Movement> {
+        let mut movements = movements.to_vec();
+        movements.extend(std::iter::repeat_n(Movement::MoveDown, rows));
+        movements
+    }
+
+    #[test]
+    fn faults() {
+        let fig = standard_figures()[0].clone();
+        let pf = Playfield::new("pf", 10, 20);
+        let start_pos = Position::new((4, 0, 0));
+        let mut analyzer = FinesseAnalyzer::new();
+
+        // Tapped all the way to the wall instead of holding
+        let tapped = with_drop(&[Movement::MoveLeft; 4], 17);
+        let end_pos = Position::new((0, 17, 0));
+        let result = analyzer
+            .analyze(&pf, &fig, start_pos, &tapped, end_pos, GRAVITY)
+            .unwrap();
+        assert_eq!(result.presses, 4);
+        assert_eq!(result.optimal_presses, 1);
+        assert_eq!(result.faults(), 3);
+
+        // Three rotations one way instead of one the other way, and a move
+        // back and forth
+        let movements = with_drop(
+            &[
+                Movement::RotateCW,
+                Movement::RotateCW,
+                Movement::RotateCW,
+                Movement::MoveRight,
+                Movement::MoveLeft,
+            ],
+            17,
+        );
+        let end_pos = Position::new((4, 17, 3));
+        let result = analyzer
+            .analyze(&pf, &fig, start_pos, &movements, end_pos, GRAVITY)
+            .unwrap();
+        assert_eq!(result.presses, 5);
+        assert_eq!(result.optimal_presses, 1);
+        assert_eq!(result.faults(), 4);
+        assert!(result.optimal_path.contains(&Movement::RotateCCW));
+
+        // Recorded while playing, a blocked move still counts
+        analyzer.start_piece(&fig, start_pos);
+        for movement in with_drop(&[Movement::MoveRight; 5], 17) {
+            analyzer.record_move(movement);
+        }
+        analyzer.record_move(Movement::MoveRight);
+        let result = analyzer
+            .finish_piece(&pf, Position::new((7, 17, 0)), GRAVITY)
+            .unwrap();
+        assert_eq!(result.presses, 6);
+        assert_eq!(result.faults(), 5);
+
+        // Held to the wall by the input script, soft dropping the rest
+        let script = [
+            InputEvent {
+                frame: 0,
+                key: Movement::MoveLeft,
+                pressed: true,
+            },
+            InputEvent {
+                frame: 12,
+                key: Movement::MoveLeft,
+                pressed: false,
+            },
+            InputEvent {
+                frame: 12,
+                key: Movement::MoveDown,
+                pressed: true,
+            },
+            InputEvent {
+                frame: 30,
+                key: Movement::MoveDown,
+                pressed: false,
+            },
+        ];
+        let result = analyzer
+            .analyze_script(&pf, &fig, start_pos, &script, end_pos, GRAVITY)
+            .unwrap();
+        assert_eq!(result.presses, 1);
+        assert_eq!(result.faults(), 0);
+
+        // Live, a held key and a tap
+        analyzer.start_piece(&fig, start_pos);
+        analyzer.record_event(&script[0]);
+        analyzer.record_event(&script[1]);
+        analyzer.record_move(Movement::RotateCW);
+        let result = analyzer
+            .finish_piece(&pf, Position::new((-1, 17, 1)), GRAVITY)
+            .unwrap();
+        assert_eq!(result.presses, 2);
+        assert_eq!(result.faults(), 0);
+
+        let stats = analyzer.stats();
+        assert_eq!(stats.pieces, 5);
+        assert_eq!(stats.faulted_pieces, 3);
+        assert_eq!(stats.faults(), 12);
+        assert!((stats.accuracy() - 2.0 / 5.0).abs() < 1e-6);
+
+        // Position that can't be reached
+        let buried = Position::new((4, 5, 0));
+        assert_eq!(
+            analyzer.analyze(&pf, &fig, start_pos, &[], buried, GRAVITY),
+            None
+        );
+        assert_eq!(analyzer.finish_piece(&pf, end_pos, GRAVITY), None);
+    }
+}
@@ -0,0 +1,367 @@
+use std::fmt;
+
+use crate::movement::Movement;
+
+//
+// Timing of the game the input script is made for, all in frames. Gravity
+// is the number of frames between each row the figure falls, counted from
+// the last time it fell (also when soft dropped). Soft drop is the number
+// of frames per row while soft drop is held, None if it isn't used.
+// An arr of 0 is handled as 1.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputTiming {
+    pub das: u32,
+    pub arr: u32,
+    pub gravity: u32,
+    pub soft_drop: Option<u32>,
+}
+
+//
+// A key pressed or released at a frame. Keys are identified by the
+// movement they make, MoveDown being soft drop.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    pub frame: u64,
+    pub key: Movement,
+    pub pressed: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptError {
+    // The movements at a row can't be made before the figure falls
+    NoTime { row: u32 },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::NoTime { row } => {
+                write!(
+                    f,
+                    "movements at row {} don't fit before the figure falls",
+                    row
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+//
+// Convert a path into key events, starting at frame 0 when the figure
+// appears. Every MoveDown of the path is expected to happen by gravity,
+// except for the drop after the last movement which is soft dropped when
+// possible. Runs of left/right are held with DAS when that's quicker than
+// tapping.
+//
+// Within a frame the game is expected to handle the key events first and
+// let the figure fall after that. One key is pressed per frame so the
+// movements are made in the order of the path.
+//
+pub fn path_to_script(
+    script: &mut Vec<InputEvent>,
+    path: &[Movement],
+    timing: &InputTiming,
+) -> Result<(), ScriptError> {
+    script.clear();
+    let gravity = u64::from(timing.gravity.max(1));
+    let das = u64::from(timing.das);
+    let arr = u64::from(timing.arr.max(1));
+
+    // Movements to make at each row
+    let mut rows: Vec<Vec<Movement>> = vec![Vec::new()];
+    for movement in path {
+        if *movement == Movement::MoveDown {
+            rows.push(Vec::new());
+        } else {
+            rows.last_mut().unwrap().push(*movement);
+        }
+    }
+    let last_row = rows.iter().rposition(|row| !row.is_empty()).unwrap_or(0);
+
+    // First frame a key can be pressed, and the key last pressed together
+    // with the frame it was released
+    let mut frame = 0;
+    let mut released: Option<(Movement, u64)> = None;
+    let mut fall_frame = 0;
+    for (row, movements) in rows.iter().enumerate().take(last_row + 1) {
+        fall_frame += gravity;
+        let mut i = 0;
+        while i < movements.len() {
+            let key = movements[i];
+            let mut run = 1;
+            if key == Movement::MoveLeft || key == Movement::MoveRight {
+                while i + run < movements.len() && movements[i + run] == key {
+                    run += 1;
+                }
+            }
+            if let Some((released_key, released_frame)) = released {
+                if released_key == key {
+                    // Pressing the same key again, needs to have been up for
+                    // a frame
+                    frame = frame.max(released_frame + 1);
+                }
+            }
+
+            // Frame of the last movement counted from the first press
+            let tapped = 2 * (run as u64 - 1);
+            let held = das + arr * (run as u64).saturating_sub(2);
+            let last_press = if run > 1 && held < tapped {
+                script.push(InputEvent {
+                    frame,
+                    key,
+                    pressed: true,
+                });
+                frame + held
+            } else {
+                for tap in 0..run as u64 {
+                    script.push(InputEvent {
+                        frame: frame + 2 * tap,
+                        key,
+                        pressed: true,
+                    });
+                    if tap + 1 < run as u64 {
+                        script.push(InputEvent {
+                            frame: frame + 2 * tap + 1,
+                            key,
+                            pressed: false,
+                        });
+                    }
+                }
+                frame + tapped
+            };
+            if last_press > fall_frame {
+                script.clear();
+                return Err(ScriptError::NoTime { row: row as u32 });
+            }
+            script.push(InputEvent {
+                frame: last_press + 1,
+                key,
+                pressed: false,
+            });
+            released = Some((key, last_press + 1));
+            frame = last_press + 1;
+            i += run;
+        }
+        // Next row starts after the figure has fallen
+        frame = frame.max(fall_frame + 1);
+    }
+
+    // Soft drop the rest of the way
+    let drop_rows = (rows.len() - 1 - last_row) as u64;
+    if let Some(soft_drop) = timing.soft_drop {
+        let soft_drop = u64::from(soft_drop.max(1));
+        if drop_rows > 0 && soft_drop < gravity {
+            let frame = match released {
+                Some((_, released_frame)) => released_frame,
+                None => 0,
+            };
+            script.push(InputEvent {
+                frame,
+                key: Movement::MoveDown,
+                pressed: true,
+            });
+            script.push(InputEvent {
+                frame: frame + soft_drop * (drop_rows - 1) + 1,
+                key: Movement::MoveDown,
+                pressed: false,
+            });
+        }
+    }
+    script.sort_by_key(|event| event.frame);
+    Ok(())
+}
+
+//
+// Turns key events into movements frame by frame, repeating held keys the
+// way a game does: left/right after DAS and then every ARR frames, soft
+// drop every soft drop frames. Rotations aren't repeated.
+//
+#[derive(Debug, Clone)]
+pub struct InputDriver {
+    timing: InputTiming,
+    script: Vec<InputEvent>,
+    next_event: usize,
+    // Keys held down together with the frame they were pressed
+    held: Vec<(Movement, u64)>,
+}
+
+impl InputDriver {
+    pub fn new(script: Vec<InputEvent>, timing: InputTiming) -> Self {
+        InputDriver {
+            timing,
+            script,
+            next_event: 0,
+            held: Vec::new(),
+        }
+    }
+
+    // Test if every event has been handled and all keys are released
+    pub fn is_done(&self) -> bool {
+        self.next_event >= self.script.len() && self.held.is_empty()
+    }
+
+    //
+    // Add the movements to make at frame. Frames are to be updated in
+    // order without skipping any.
+    //
+    pub fn update(&mut self, frame: u64, movements: &mut Vec<Movement>) {
+        while let Some(event) = self.script.get(self.next_event) {
+            if event.frame > frame {
+                break;
+            }
+            if event.pressed {
+                self.held.push((event.key, frame));
+                movements.push(event.key);
+            } else {
+                self.held.retain(|(key, _)| *key != event.key);
+            }
+            self.next_event += 1;
+        }
+        let das = u64::from(self.timing.das);
+        let arr = u64::from(self.timing.arr.max(1));
+        for (key, pressed) in &self.held {
+            let held_frames = frame - pressed;
+            if held_frames == 0 {
+                continue;
+            }
+            let repeat = match key {
+                Movement::MoveLeft | Movement::MoveRight => {
+                    held_frames >= das && (held_frames - das) % arr == 0
+                }
+                Movement::MoveDown => match self.timing.soft_drop {
+                    Some(soft_drop) => held_frames % u64::from(soft_drop.max(1)) == 0,
+                    None => false,
+                },
+                _ => false,
+            };
+            if repeat {
+                movements.push(*key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::cost_model::KeyPresses;
+    use crate::figure::{standard_figures, Figure};
+    use crate::find_path::{FindPath, SearchGravity};
+    use crate::playfield::Playfield;
+    use crate::position::Position;
+
+    //
+    // Play the script on a game handling input before gravity each frame.
+    // Returns the movements made and where the figure ended up.
+    //
+    fn play(
+        pf: &Playfield,
+        fig: &Figure,
+        start_pos: Position,
+        script: &[InputEvent],
+        timing: &InputTiming,
+    ) -> (Vec<Movement>, Position) {
+        let mut driver = InputDriver::new(script.to_vec(), *timing);
+        let mut pos = start_pos;
+        let mut made = Vec::new();
+        let mut movements = Vec::new();
+        let mut last_fall = 0;
+        for frame in 0..1000 {
+            movements.clear();
+            driver.update(frame, &mut movements);
+            if frame - last_fall >= u64::from(timing.gravity) {
+                movements.push(Movement::MoveDown);
+            }
+            for movement in &movements {
+                let mut moved = pos.apply_move(*movement);
+                moved.normalize_dir(fig.num_faces());
+                if fig.test_collision(pf, moved) {
+                    continue;
+                }
+                if *movement == Movement::MoveDown {
+                    last_fall = frame;
+                }
+                pos = moved;
+                made.push(*movement);
+            }
+            if driver.is_done() && fig.test_collision(pf, pos.apply_move(Movement::MoveDown)) {
+                break;
+            }
+        }
+        (made, pos)
+    }
+
+    #[test]
+    fn play_paths() {
+        let fig = standard_figures()[0].clone();
+        let mut pf = Playfield::new("pf", 10, 12);
+        for x in 0..4 {
+            pf.set_block((x, 9).into(), Block::Set(2));
+        }
+        let start_pos = Position::new((4, 0, 0));
+        let timings = [
+            InputTiming {
+                das: 6,
+                arr: 1,
+                gravity: 12,
+                soft_drop: Some(2),
+            },
+            InputTiming {
+                das: 3,
+                arr: 1,
+                gravity: 10,
+                soft_drop: None,
+            },
+        ];
+
+        let mut reachable = Vec::new();
+        let mut find_path = FindPath::new();
+        find_path.set_cost_model(Box::new(KeyPresses));
+        find_path.search_all(
+            &mut reachable,
+            &pf,
+            &fig,
+            start_pos,
+            SearchGravity::moves_per_row(1),
+        );
+        assert!(reachable.iter().any(|r| r.pos == Position::new((0, 9, 0))));
+
+        let mut script = Vec::new();
+        for timing in &timings {
+            for r in &reachable {
+                path_to_script(&mut script, &r.path, timing).unwrap();
+                let (made, pos) = play(&pf, &fig, start_pos, &script, timing);
+                assert_eq!(made, r.path);
+                assert_eq!(pos, r.pos);
+            }
+        }
+
+        // Holding is quicker than tapping with a short DAS
+        let path = [Movement::MoveLeft; 4];
+        path_to_script(&mut script, &path, &timings[1]).unwrap();
+        assert_eq!(script.len(), 2);
+
+        // Too many movements before the figure falls
+        let timing = InputTiming {
+            das: 20,
+            arr: 1,
+            gravity: 4,
+            soft_drop: None,
+        };
+        let path = [
+            Movement::RotateCW,
+            Movement::MoveLeft,
+            Movement::MoveLeft,
+            Movement::MoveLeft,
+        ];
+        assert_eq!(
+            path_to_script(&mut script, &path, &timing),
+            Err(ScriptError::NoTime { row: 0 })
+        );
+    }
+}
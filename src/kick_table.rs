@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use crate::board::Board;
+use crate::figure::Figure;
+use crate::movement::Movement;
+use crate::position::Position;
+
+//
+// Wall kicks of a figure. For each rotation transition (from direction,
+// to direction) a list of (x, y) offsets is tested in order and the first
+// offset where the rotated figure doesn't collide is used. A transition
+// without kicks only rotates in place.
+//
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct KickTable {
+    num_faces: u8,
+    kicks: HashMap<(i32, i32), Vec<(i32, i32)>>,
+}
+
+impl KickTable {
+    pub fn new(fig: &Figure) -> Self {
+        KickTable {
+            num_faces: fig.num_faces(),
+            kicks: HashMap::new(),
+        }
+    }
+
+    fn normalize_dir(&self, dir: i32) -> i32 {
+        let mut pos = Position::new((0, 0, dir));
+        pos.normalize_dir(self.num_faces);
+        pos.dir()
+    }
+
+    //
+    // Set the offsets to test when rotating from one direction to another
+    //
+    pub fn set_kicks(&mut self, from_dir: i32, to_dir: i32, offsets: &[(i32, i32)]) {
+        let transition = (self.normalize_dir(from_dir), self.normalize_dir(to_dir));
+        self.kicks.insert(transition, offsets.to_vec());
+    }
+
+    pub fn kicks(&self, from_dir: i32, to_dir: i32) -> &[(i32, i32)] {
+        let transition = (self.normalize_dir(from_dir), self.normalize_dir(to_dir));
+        match self.kicks.get(&transition) {
+            Some(offsets) => offsets,
+            None => &[(0, 0)],
+        }
+    }
+
+    //
+    // Rotate figure at position, returns the resulting position or None if
+    // every kick collides
+    //
+    pub fn rotate<B: Board>(
+        &self,
+        pf: &B,
+        fig: &Figure,
+        pos: Position,
+        movement: Movement,
+    ) -> Option<Position> {
+        let mut rotated = pos.apply_move(movement);
+        rotated.normalize_dir(fig.num_faces());
+        self.kicks(pos.dir(), rotated.dir())
+            .iter()
+            .map(|(x, y)| Position::new((rotated.x() + x, rotated.y() + y, rotated.dir())))
+            .find(|kicked| !fig.test_collision(pf, *kicked))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::playfield::Playfield;
+
+    macro_rules! bl {
+        ($x:expr) => {
+            match $x {
+                0 => Block::Clear,
+                _ => Block::Set($x),
+            }
+        };
+    }
+
+    #[test]
+    fn kick_from_wall() {
+        let fig = Figure::new_from_face(
+            "7",
+            &[
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+                &[bl!(0), bl!(7), bl!(0)],
+            ],
+        );
+        let pf = Playfield::new("pf", 6, 10);
+        let mut kicks = KickTable::new(&fig);
+        kicks.set_kicks(0, 1, &[(0, 0), (-1, 0), (-2, 0)]);
+
+        // Vertical against the right wall, no room to rotate in place
+        let pos = Position::new((4, 2, 0));
+        assert_eq!(
+            kicks.rotate(&pf, &fig, pos, Movement::RotateCW),
+            Some(Position::new((2, 2, 1)))
+        );
+        // Not kicked when there's room
+        let pos = Position::new((1, 2, 0));
+        assert_eq!(
+            kicks.rotate(&pf, &fig, pos, Movement::RotateCW),
+            Some(Position::new((1, 2, 1)))
+        );
+        // No kicks set for rotating back
+        let pos = Position::new((2, 8, 1));
+        assert_eq!(kicks.rotate(&pf, &fig, pos, Movement::RotateCCW), None);
+        assert_eq!(kicks.kicks(1, 0), &[(0, 0)]);
+        assert_eq!(kicks.kicks(-2, 5), kicks.kicks(0, 1));
+    }
+}
@@ -1,12 +1,24 @@
 //#![feature(test)]
 //extern crate test;
+pub mod ai;
+pub mod bitboard;
 pub mod block;
+pub mod board;
+pub mod cost_model;
 pub mod figure;
 pub mod find_path;
 pub mod find_placement;
+pub mod finesse;
+pub mod input_script;
+pub mod kick_table;
 pub mod movement;
+pub mod occupancy_grid;
+pub mod perfect_clear;
 pub mod playfield;
 pub mod position;
+pub mod randomizer;
+pub mod regions;
+pub mod zobrist;
 
 mod matrix2;
 mod matrix3;
@@ -61,7 +61,7 @@ where
     fn index_from_point(&self, point: Vec2<i32>) -> usize {
         let p0 = point.x as usize;
         let p1 = point.y as usize;
-        (p1 * self.width() as usize + p0)
+        p1 * self.width() as usize + p0
     }
 
     pub fn get(&self, point: Vec2<i32>) -> &T {
@@ -230,12 +230,11 @@ mod tests {
             &[false, false, false],
             &[false, true, false],
         ]);
-        assert_eq!(
-            m1.test_overlap(Vec2 { x: 0, y: 0 }, &no_overlap, |a, b| match a {
-                Some(ai) => *ai == true && *b == true,
-                None => *b == true,
-            }),
-            false
+        assert!(
+            !m1.test_overlap(Vec2 { x: 0, y: 0 }, &no_overlap, |a, b| match a {
+                Some(ai) => *ai && *b,
+                None => *b,
+            })
         );
 
         // Will overlap m1
@@ -244,12 +243,11 @@ mod tests {
             &[false, true, false],
             &[false, true, false],
         ]);
-        assert_eq!(
+        assert!(
             m1.test_overlap(Vec2 { x: 0, y: 0 }, &overlap, |a, b| match a {
-                Some(ai) => *ai == true && *b == true,
-                None => *b == true,
-            }),
-            true
+                Some(ai) => *ai && *b,
+                None => *b,
+            })
         );
     }
 }
@@ -0,0 +1,67 @@
+use crate::board::Board;
+use crate::matrix2::Matrix2;
+use crate::playfield::Playfield;
+use crate::vec2::Vec2;
+
+//
+// Colour-less playfield only keeping track of which blocks are occupied
+//
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OccupancyGrid {
+    blocks: Matrix2<bool>,
+}
+
+impl OccupancyGrid {
+    pub fn new(width: u32, height: u32) -> OccupancyGrid {
+        OccupancyGrid {
+            blocks: Matrix2::from_size(width, height, false),
+        }
+    }
+    pub fn from_playfield(pf: &Playfield) -> OccupancyGrid {
+        let mut grid = OccupancyGrid::new(pf.width(), pf.height());
+        for y in 0..pf.height() as i32 {
+            for x in 0..pf.width() as i32 {
+                if pf.block_is_set((x, y).into()) {
+                    grid.blocks.set((x, y).into(), true);
+                }
+            }
+        }
+        grid
+    }
+}
+
+impl Board for OccupancyGrid {
+    fn width(&self) -> u32 {
+        self.blocks.width()
+    }
+    fn height(&self) -> u32 {
+        self.blocks.height()
+    }
+    fn occupied(&self, point: Vec2<i32>) -> bool {
+        !self.blocks.contains(point) || *self.blocks.get(point)
+    }
+    fn set(&mut self, point: Vec2<i32>, _id: u8) {
+        if self.blocks.contains(point) {
+            self.blocks.set(point, true);
+        }
+    }
+    fn clear(&mut self, point: Vec2<i32>) {
+        if self.blocks.contains(point) {
+            self.blocks.set(point, false);
+        }
+    }
+    fn throw_line(&mut self, line: u32) {
+        for y in (0..=line as i32).rev() {
+            for x in 0..self.width() as i32 {
+                let above = y >= 1 && *self.blocks.get((x, y - 1).into());
+                self.blocks.set((x, y).into(), above);
+            }
+        }
+    }
+    fn copy_from(&mut self, other: &Self) {
+        if self.height() != other.height() || self.width() != other.width() {
+            panic!("can't copy occupancy grid of different sizes");
+        }
+        self.blocks.clone_from_slice(&other.blocks);
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::bitboard::BitBoard;
+use crate::figure::Figure;
+use crate::find_path::{FindPath, Reachable, SearchGravity};
+use crate::movement::Movement;
+use crate::playfield::Playfield;
+use crate::position::Position;
+use crate::regions::Regions;
+
+//
+// A perfect clear has to be made within max_lines lines, i.e. no block may
+// be placed above the lowest max_lines rows (minus the lines cleared so
+// far). The search stops after max_solutions solutions if set.
+//
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PcConfig {
+    pub max_lines: u32,
+    pub use_hold: bool,
+    pub max_solutions: Option<usize>,
+    pub gravity: SearchGravity,
+}
+
+impl Default for PcConfig {
+    fn default() -> Self {
+        PcConfig {
+            max_lines: 4,
+            use_hold: true,
+            max_solutions: None,
+            gravity: SearchGravity::moves_per_row(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcPlacement {
+    pub figure: String,
+    // Hold the current figure and play the figure in hold, or the next
+    // figure if hold is empty
+    pub hold: bool,
+    pub pos: Position,
+    // Path from the start position
+    pub path: Vec<Movement>,
+}
+
+//
+// Placements in the order they are made, ending with an empty board
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct PcSolution {
+    pub placements: Vec<PcPlacement>,
+}
+
+// Search state without the placements leading to it
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct State {
+    board: BitBoard,
+    // Index of the figure in hold and of the next figure to play, the
+    // figure in hold is stored after the queue
+    hold: Option<usize>,
+    next: usize,
+    cleared: u32,
+}
+
+//
+// Finds every sequence of placements of the current figure, hold and the
+// queue that empties the board. Placements have to be reachable with the
+// path search from the start position.
+//
+// Boards that can't be emptied by the figures left are pruned: by the
+// number of free cells, by the size of each free region (when all figures
+// have the same number of blocks) and by checkerboard parity, where each
+// figure can only even out the difference by what its faces cover of one
+// color more than the other (2 for T, 0 for the others).
+//
+// Clearing a line moves the cells above it down a row, which can join
+// regions and swaps the colors of the cells. Only regions no clear can
+// join with another are checked, and parity only when the free cells are
+// all in one row so no line is cleared before the last figure.
+//
+pub struct PerfectClearFinder {
+    config: PcConfig,
+    searched: usize,
+
+    // Some cache variables
+    find_path: FindPath,
+    regions: Regions,
+    failed: HashSet<State>,
+}
+
+impl PerfectClearFinder {
+    pub fn new(config: PcConfig) -> Self {
+        PerfectClearFinder {
+            config,
+            searched: 0,
+            find_path: FindPath::new(),
+            regions: Regions::new(),
+            failed: HashSet::new(),
+        }
+    }
+
+    pub fn config(&self) -> &PcConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: PcConfig) {
+        self.config = config;
+    }
+
+    pub fn find_path_mut(&mut self) -> &mut FindPath {
+        &mut self.find_path
+    }
+
+    //
+    // Number of states searched in the last find
+    //
+    pub fn searched_states(&self) -> usize {
+        self.searched
+    }
+
+    pub fn find(
+        &mut self,
+        solutions: &mut Vec<PcSolution>,
+        pf: &Playfield,
+        current: &Figure,
+        hold: Option<&Figure>,
+        queue: &[Figure],
+        start_pos: Position,
+    ) {
+        solutions.clear();
+        self.failed.clear();
+        self.searched = 0;
+        let figures: Vec<&Figure> = std::iter::once(current)
+            .chain(queue.iter())
+            .chain(hold)
+            .collect();
+        let queue_len = 1 + queue.len();
+        let state = State {
+            board: BitBoard::from_playfield(pf),
+            hold: hold.map(|_| queue_len),
+            next: 0,
+            cleared: 0,
+        };
+        if Self::blocks_above(&state.board, self.zone_top(&state)) {
+            return;
+        }
+        let search = Search {
+            figures,
+            queue_len,
+            start_pos,
+            parity: std::iter::once(current)
+                .chain(queue.iter())
+                .chain(hold)
+                .map(parity_imbalance)
+                .collect(),
+        };
+        let mut placements = Vec::new();
+        self.search(&search, &state, &mut placements, solutions);
+    }
+
+    // Top row of the area where blocks may be placed
+    fn zone_top(&self, state: &State) -> i32 {
+        state.board.height() as i32 - (self.config.max_lines as i32 - state.cleared as i32)
+    }
+
+    fn blocks_above(board: &BitBoard, zone_top: i32) -> bool {
+        (0..zone_top.max(0))
+            .any(|y| (0..board.width() as i32).any(|x| board.block_is_set((x, y).into())))
+    }
+
+    fn done(&self, solutions: &[PcSolution]) -> bool {
+        self.config
+            .max_solutions
+            .is_some_and(|max| solutions.len() >= max)
+    }
+
+    // Search all placements from state, returns if any solution was found
+    fn search(
+        &mut self,
+        search: &Search,
+        state: &State,
+        placements: &mut Vec<PcPlacement>,
+        solutions: &mut Vec<PcSolution>,
+    ) -> bool {
+        if state.next >= search.queue_len || self.done(solutions) || self.failed.contains(state) {
+            return false;
+        }
+        self.searched += 1;
+        let figures = &search.figures;
+        let current = figures[state.next];
+
+        // Figure to play, with the hold and next figure after it
+        let mut options = vec![(state.next, state.hold, state.next + 1, false)];
+        if self.config.use_hold {
+            match state.hold {
+                Some(held) if figures[held].name() != current.name() => {
+                    options.push((held, Some(state.next), state.next + 1, true));
+                }
+                None if state.next + 1 < search.queue_len
+                    && figures[state.next + 1].name() != current.name() =>
+                {
+                    options.push((state.next + 1, Some(state.next), state.next + 2, true));
+                }
+                _ => {}
+            }
+        }
+
+        let mut found = false;
+        let mut reachable = Vec::new();
+        for (fig, hold, next, held) in options {
+            let figure = figures[fig];
+            self.find_path.search_all(
+                &mut reachable,
+                &state.board,
+                figure,
+                search.start_pos,
+                self.config.gravity,
+            );
+            for (cells, reachable) in unique_by_cells(figure, &reachable) {
+                if self.done(solutions) {
+                    break;
+                }
+                if cells.iter().any(|(_, y)| *y < self.zone_top(state)) {
+                    continue;
+                }
+                let mut board = state.board.clone();
+                figure.place(&mut board, reachable.pos);
+                let lines = board.locked_lines();
+                for line in &lines {
+                    board.throw_line(*line);
+                }
+                let next_state = State {
+                    board,
+                    hold,
+                    next,
+                    cleared: state.cleared + lines.len() as u32,
+                };
+
+                placements.push(PcPlacement {
+                    figure: figure.name().clone(),
+                    hold: held,
+                    pos: reachable.pos,
+                    path: reachable.path.clone(),
+                });
+                if next_state.board.rows().iter().all(|row| *row == 0) {
+                    solutions.push(PcSolution {
+                        placements: placements.clone(),
+                    });
+                    found = true;
+                } else if !self.prune(search, &next_state) {
+                    found |= self.search(search, &next_state, placements, solutions);
+                }
+                placements.pop();
+            }
+        }
+        if !found {
+            self.failed.insert(state.clone());
+        }
+        found
+    }
+
+    // Test if the board can't be emptied with the figures left
+    fn prune(&mut self, search: &Search, state: &State) -> bool {
+        let zone_top = self.zone_top(state);
+        if zone_top >= state.board.height() as i32 {
+            return true;
+        }
+        let available = (state.next..search.queue_len).chain(state.hold);
+        let (mut cells, mut parity) = (0, 0);
+        for fig in available {
+            cells += search.figures[fig].face(0).len() as u32;
+            parity += search.parity[fig];
+        }
+        self.regions.compute_rows(&state.board, zone_top);
+        if self.regions.free_cells() > cells
+            || (self.regions.free_rows() <= 1
+                && self.regions.checkerboard_parity().unsigned_abs() > parity)
+        {
+            return true;
+        }
+        match search.cells_per_figure() {
+            Some(size) => self
+                .regions
+                .sizes()
+                .iter()
+                .enumerate()
+                .any(|(region, cells)| !self.regions.is_linked(region) && cells % size != 0),
+            None => false,
+        }
+    }
+}
+
+struct Search<'a> {
+    figures: Vec<&'a Figure>,
+    queue_len: usize,
+    start_pos: Position,
+    // Checkerboard parity imbalance each figure can make
+    parity: Vec<u32>,
+}
+
+impl Search<'_> {
+    // Number of blocks of every figure, None if they differ
+    fn cells_per_figure(&self) -> Option<u32> {
+        let size = self.figures[0].face(0).len();
+        if self.figures.iter().all(|fig| fig.face(0).len() == size) {
+            Some(size as u32)
+        } else {
+            None
+        }
+    }
+}
+
+// Largest difference between the blocks of a face on the two checkerboard
+// colors
+fn parity_imbalance(fig: &Figure) -> u32 {
+    fig.iter_faces()
+        .map(|face| {
+            let black = face.iter().filter(|(x, y, _)| (x + y) % 2 == 0).count() as i32;
+            (2 * black - face.len() as i32).unsigned_abs()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+// The cheapest reachable position for each set of cells the figure can
+// lock, positions of symmetric faces locking the same cells are the same
+// placement
+fn unique_by_cells<'a>(
+    fig: &Figure,
+    reachable: &'a [Reachable],
+) -> Vec<(Vec<(i32, i32)>, &'a Reachable)> {
+    let mut by_cells: HashMap<Vec<(i32, i32)>, usize> = HashMap::new();
+    let mut unique: Vec<(Vec<(i32, i32)>, &Reachable)> = Vec::new();
+    for r in reachable {
+        let mut cells: Vec<(i32, i32)> = fig
+            .face(r.pos.dir())
+            .iter()
+            .map(|(x, y, _)| (r.pos.x() + i32::from(*x), r.pos.y() + i32::from(*y)))
+            .collect();
+        cells.sort();
+        match by_cells.get(&cells) {
+            Some(index) if unique[*index].1.cost <= r.cost => {}
+            Some(index) => unique[*index].1 = r,
+            None => {
+                by_cells.insert(cells.clone(), unique.len());
+                unique.push((cells, r));
+            }
+        }
+    }
+    unique
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::figure::standard_figures;
+    use crate::find_placement::find_placement;
+
+    fn fig_t() -> Figure {
+        standard_figures()[0].clone()
+    }
+
+    fn fig_j() -> Figure {
+        standard_figures()[1].clone()
+    }
+
+    fn fig_l() -> Figure {
+        standard_figures()[2].clone()
+    }
+
+    fn fig_o() -> Figure {
+        standard_figures()[3].clone()
+    }
+
+    fn fig_s() -> Figure {
+        standard_figures()[4].clone()
+    }
+
+    fn fig_i() -> Figure {
+        standard_figures()[6].clone()
+    }
+
+    // Bottom two rows filled except for the free cells
+    fn two_lines(free: &[(i32, i32)]) -> Playfield {
+        let mut pf = Playfield::new("pf", 10, 20);
+        for y in 18..20 {
+            for x in 0..10 {
+                if !free.contains(&(x, y)) {
+                    pf.set_block((x, y).into(), Block::Set(1));
+                }
+            }
+        }
+        pf
+    }
+
+    fn replay(pf: &Playfield, solution: &PcSolution, figures: &[Figure], start_pos: Position) {
+        let mut pf = pf.clone();
+        for placement in &solution.placements {
+            let fig = figures
+                .iter()
+                .find(|fig| *fig.name() == placement.figure)
+                .unwrap();
+            let mut pos = start_pos;
+            for movement in &placement.path {
+                pos = pos.apply_move(*movement);
+                pos.normalize_dir(fig.num_faces());
+                assert!(!fig.test_collision(&pf, pos));
+            }
+            assert_eq!(pos, placement.pos);
+            fig.place(&mut pf, pos);
+            for line in pf.locked_lines() {
+                pf.throw_line(line);
+            }
+        }
+        assert_eq!(pf.count_locked_lines(), 0);
+        assert!((0..20).all(|y| (0..10).all(|x| !pf.block_is_set((x, y).into()))));
+    }
+
+    #[test]
+    fn solutions() {
+        let figures = [fig_t(), fig_o(), fig_s(), fig_i()];
+        let start_pos = (4, 0, 0).into();
+        let config = PcConfig {
+            max_lines: 2,
+            ..PcConfig::default()
+        };
+        let mut finder = PerfectClearFinder::new(config);
+        let mut solutions = Vec::new();
+
+        // A 4x2 hole filled by two O in either order, or by two I
+        let pf = two_lines(&[
+            (6, 18),
+            (7, 18),
+            (8, 18),
+            (9, 18),
+            (6, 19),
+            (7, 19),
+            (8, 19),
+            (9, 19),
+        ]);
+        finder.find(&mut solutions, &pf, &fig_o(), None, &[fig_o()], start_pos);
+        assert_eq!(solutions.len(), 2);
+        for solution in &solutions {
+            replay(&pf, solution, &figures, start_pos);
+        }
+        finder.find(&mut solutions, &pf, &fig_i(), None, &[fig_i()], start_pos);
+        assert_eq!(solutions.len(), 1);
+        replay(&pf, &solutions[0], &figures, start_pos);
+
+        // Only the O in hold fits the 2x2 hole
+        let pf = two_lines(&[(4, 18), (5, 18), (4, 19), (5, 19)]);
+        finder.find(
+            &mut solutions,
+            &pf,
+            &fig_s(),
+            Some(&fig_o()),
+            &[],
+            start_pos,
+        );
+        assert_eq!(solutions.len(), 1);
+        assert!(solutions[0].placements[0].hold);
+        assert_eq!(solutions[0].placements[0].figure, "4");
+        finder.set_config(PcConfig {
+            use_hold: false,
+            ..config
+        });
+        finder.find(
+            &mut solutions,
+            &pf,
+            &fig_s(),
+            Some(&fig_o()),
+            &[],
+            start_pos,
+        );
+        assert!(solutions.is_empty());
+
+        // Nothing can be placed above the lines
+        finder.set_config(PcConfig {
+            max_lines: 1,
+            ..config
+        });
+        finder.find(&mut solutions, &pf, &fig_o(), None, &[], start_pos);
+        assert!(solutions.is_empty());
+    }
+
+    #[test]
+    fn unreachable() {
+        // The hole is covered, an I fits there but can't get there
+        let pf = two_lines(&[(0, 19), (1, 19), (2, 19), (3, 19)]);
+        let mut placements = Vec::new();
+        find_placement(&mut placements, &pf, &fig_i());
+        assert!(placements.contains(&(0, 18, 1).into()));
+
+        let mut finder = PerfectClearFinder::new(PcConfig {
+            max_lines: 2,
+            ..PcConfig::default()
+        });
+        let mut solutions = Vec::new();
+        finder.find(&mut solutions, &pf, &fig_i(), None, &[], (4, 0, 0).into());
+        assert!(solutions.is_empty());
+    }
+
+    #[test]
+    fn pruning() {
+        // Single cells apart, two of each color
+        let mut regions = Regions::new();
+        let board = BitBoard::from_playfield(&two_lines(&[(0, 18), (2, 19), (5, 19), (9, 18)]));
+        regions.compute_rows(&board, 18);
+        assert_eq!(regions.sizes(), &[1, 1, 1, 1]);
+        assert_eq!(regions.checkerboard_parity(), 0);
+        let board = BitBoard::from_playfield(&two_lines(&[(0, 18), (2, 18), (1, 19), (3, 19)]));
+        regions.compute_rows(&board, 18);
+        assert_eq!(regions.checkerboard_parity(), 4);
+
+        assert_eq!(parity_imbalance(&fig_t()), 2);
+        assert_eq!(parity_imbalance(&fig_s()), 0);
+        assert_eq!(parity_imbalance(&fig_i()), 0);
+
+        // Only a T fills the hole, the S has to be held
+        let pf = two_lines(&[(3, 18), (4, 18), (5, 18), (4, 19)]);
+        let mut finder = PerfectClearFinder::new(PcConfig {
+            max_lines: 2,
+            ..PcConfig::default()
+        });
+        let mut solutions = Vec::new();
+        finder.find(
+            &mut solutions,
+            &pf,
+            &fig_s(),
+            None,
+            &[fig_t()],
+            (4, 0, 0).into(),
+        );
+        assert_eq!(solutions.len(), 1);
+        assert!(solutions[0].placements[0].hold);
+        finder.find(
+            &mut solutions,
+            &pf,
+            &fig_s(),
+            None,
+            &[fig_i()],
+            (4, 0, 0).into(),
+        );
+        assert!(solutions.is_empty());
+
+        // Lines 18 and 19 each miss a cell, covered by the other line. The
+        // corner left by the I looks like a region of 3 cells until the J
+        // clears line 18 and opens it to the L.
+        let mut pf = Playfield::new("pf", 10, 20);
+        for x in 0..10 {
+            if x != 9 {
+                pf.set_block((x, 18).into(), Block::Set(1));
+            }
+            if x != 0 {
+                pf.set_block((x, 19).into(), Block::Set(1));
+            }
+        }
+        let figures = [fig_i(), fig_j(), fig_l()];
+        let mut finder = PerfectClearFinder::new(PcConfig {
+            max_lines: 3,
+            use_hold: false,
+            ..PcConfig::default()
+        });
+        finder.find(
+            &mut solutions,
+            &pf,
+            &fig_i(),
+            None,
+            &[fig_j(), fig_l()],
+            (4, 0, 0).into(),
+        );
+        assert!(!solutions.is_empty());
+        for solution in &solutions {
+            replay(&pf, solution, &figures, (4, 0, 0).into());
+        }
+    }
+}
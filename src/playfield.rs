@@ -1,12 +1,29 @@
 use crate::block::Block;
+use crate::board::Board;
 use crate::matrix2::Matrix2;
 use crate::vec2::Vec2;
+use crate::zobrist;
+
+//
+// How blocks above cleared lines fall
+//
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gravity {
+    // Everything above a cleared line moves down one row
+    Naive,
+    // Connected blocks with the same id fall as a unit until they rest
+    Sticky,
+    // All connected blocks fall as a unit until they rest
+    Cascade,
+}
 
 #[derive(Debug, Clone)]
 pub struct Playfield {
     pf_name: String,
     blocks: Matrix2<Block>,
     outside_block: Block,
+    // Zobrist hash of the set blocks, kept up to date by every change
+    hash: u64,
 }
 
 impl Playfield {
@@ -15,6 +32,7 @@ impl Playfield {
             pf_name: name.to_owned(),
             blocks: Matrix2::from_size(width, height, Block::Clear),
             outside_block: Block::Set(0),
+            hash: 0,
         }
     }
     pub fn copy(&mut self, other: &Playfield) {
@@ -22,19 +40,49 @@ impl Playfield {
             panic!("can't copy playfield of different sizes");
         }
         self.blocks.clone_from_slice(&other.blocks);
+        self.hash = other.hash;
+    }
+    pub fn name(&self) -> &String {
+        &self.pf_name
+    }
+    //
+    // Build a horizontally mirrored copy of the playfield
+    //
+    pub fn mirrored(&self) -> Playfield {
+        let mut pf = self.clone();
+        let width = self.width() as i32;
+        for y in 0..self.height() as i32 {
+            for x in 0..width {
+                pf.set_block(
+                    (width - 1 - x, y).into(),
+                    self.get_block((x, y).into()).clone(),
+                );
+            }
+        }
+        pf
     }
     pub fn get_block(&self, point: Vec2<i32>) -> &Block {
         if !self.blocks.contains(point) {
             &self.outside_block
         } else {
-            &self.blocks.get(point)
+            self.blocks.get(point)
         }
     }
     pub fn set_block(&mut self, point: Vec2<i32>, block: Block) {
         if self.blocks.contains(point) {
+            if self.blocks.get(point).is_set() != block.is_set() {
+                self.hash ^= zobrist::cell_key(point);
+            }
             self.blocks.set(point, block);
         }
     }
+    //
+    // Zobrist hash of the blocks, equal playfields have equal hashes no
+    // matter how they were built
+    //
+    pub fn zobrist_hash(&self) -> u64 {
+        self.hash
+    }
     pub fn blocks(&self) -> &Matrix2<Block> {
         &self.blocks
     }
@@ -58,7 +106,7 @@ impl Playfield {
         for (x, y, id) in face {
             let x = i32::from(*x) + point.x;
             let y = i32::from(*y) + point.y;
-            self.blocks.set((x, y).into(), Block::Set(*id));
+            self.set_block((x, y).into(), Block::Set(*id));
         }
     }
 
@@ -66,7 +114,7 @@ impl Playfield {
         for (x, y, _id) in face {
             let x = i32::from(*x) + point.x;
             let y = i32::from(*y) + point.y;
-            self.blocks.set((x, y).into(), Block::Clear);
+            self.set_block((x, y).into(), Block::Clear);
         }
     }
 
@@ -134,6 +182,159 @@ impl Playfield {
             y -= 1;
         }
     }
+
+    //
+    // Remove all full lines and let the remaining blocks fall according to
+    // gravity. Each step of a chain reaction is returned as the lines
+    // cleared in that step (in playfield rows at the time of the step).
+    //
+    pub fn clear_lines(&mut self, gravity: Gravity) -> Vec<Vec<u32>> {
+        let mut steps = Vec::new();
+        loop {
+            let lines = self.locked_lines();
+            if lines.is_empty() {
+                break;
+            }
+            match gravity {
+                Gravity::Naive => {
+                    for line in &lines {
+                        self.throw_line(*line);
+                    }
+                }
+                Gravity::Sticky | Gravity::Cascade => {
+                    self.set_lines(&lines, &Block::Clear);
+                    self.settle(gravity);
+                }
+            }
+            steps.push(lines);
+        }
+        steps
+    }
+
+    //
+    // Label the connected groups of set blocks. Sticky gravity only
+    // connects blocks with the same id.
+    //
+    fn label_groups(&self, gravity: Gravity) -> Matrix2<Option<usize>> {
+        let mut labels = Matrix2::from_size(self.width(), self.height(), None);
+        let mut num_groups = 0;
+        let mut stack: Vec<Vec2<i32>> = Vec::new();
+        for y in 0..self.height() as i32 {
+            for x in 0..self.width() as i32 {
+                let start = (x, y).into();
+                if !self.block_is_set(start) || labels.get(start).is_some() {
+                    continue;
+                }
+                labels.set(start, Some(num_groups));
+                stack.push(start);
+                while let Some(point) = stack.pop() {
+                    let block = self.get_block(point);
+                    for (dx, dy) in &[(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                        let next: Vec2<i32> = (point.x + dx, point.y + dy).into();
+                        if !self.contains(next) || labels.get(next).is_some() {
+                            continue;
+                        }
+                        let next_block = self.get_block(next);
+                        let connected = match gravity {
+                            Gravity::Sticky => next_block == block,
+                            _ => next_block.is_set(),
+                        };
+                        if connected {
+                            labels.set(next, Some(num_groups));
+                            stack.push(next);
+                        }
+                    }
+                }
+                num_groups += 1;
+            }
+        }
+        labels
+    }
+
+    //
+    // Let groups of blocks fall one row at a time until all of them rest
+    // on the bottom or on another group
+    //
+    fn settle(&mut self, gravity: Gravity) {
+        let width = self.width() as i32;
+        let height = self.height() as i32;
+        loop {
+            let labels = self.label_groups(gravity);
+            let num_groups = labels
+                .items()
+                .iter()
+                .filter_map(|label| *label)
+                .max()
+                .map_or(0, |max| max + 1);
+            let mut can_fall = vec![true; num_groups];
+            for y in 0..height {
+                for x in 0..width {
+                    if let Some(group) = *labels.get((x, y).into()) {
+                        let below = (x, y + 1).into();
+                        let below_group = if self.contains(below) {
+                            *labels.get(below)
+                        } else {
+                            None
+                        };
+                        if self.block_is_set(below) && below_group != Some(group) {
+                            can_fall[group] = false;
+                        }
+                    }
+                }
+            }
+            if !can_fall.iter().any(|fall| *fall) {
+                break;
+            }
+            // Move the falling groups one row down, starting from the bottom
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    if let Some(group) = *labels.get((x, y).into()) {
+                        if can_fall[group] {
+                            let block = self.get_block((x, y).into()).clone();
+                            self.set_block((x, y + 1).into(), block);
+                            self.set_block((x, y).into(), Block::Clear);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Board for Playfield {
+    fn width(&self) -> u32 {
+        Playfield::width(self)
+    }
+    fn height(&self) -> u32 {
+        Playfield::height(self)
+    }
+    fn occupied(&self, point: Vec2<i32>) -> bool {
+        self.block_is_set(point)
+    }
+    fn set(&mut self, point: Vec2<i32>, id: u8) {
+        self.set_block(point, Block::Set(id));
+    }
+    fn clear(&mut self, point: Vec2<i32>) {
+        self.clear_block(point);
+    }
+    fn throw_line(&mut self, line: u32) {
+        Playfield::throw_line(self, line);
+    }
+    fn copy_from(&mut self, other: &Self) {
+        self.copy(other);
+    }
+    fn line_is_full(&self, line: u32) -> bool {
+        self.blocks
+            .row_iter()
+            .nth(line as usize)
+            .is_some_and(|row| row.iter().all(|b| b.is_set()))
+    }
+    fn locked_lines(&self) -> Vec<u32> {
+        Playfield::locked_lines(self)
+    }
+    fn count_locked_lines(&self) -> u32 {
+        Playfield::count_locked_lines(self)
+    }
 }
 
 #[cfg(test)]
@@ -170,4 +371,72 @@ mod tests {
         // first locked line is now 2
         assert_eq!(pf.locked_lines()[0], 2);
     }
+    #[test]
+    fn mirrored() {
+        let mut pf = Playfield::new("pf1", 5, 3);
+        pf.set_block((0, 2).into(), Block::Set(1));
+        pf.set_block((1, 2).into(), Block::Set(2));
+        pf.set_block((4, 0).into(), Block::Set(3));
+        let mirrored = pf.mirrored();
+        assert_eq!(*mirrored.get_block((4, 2).into()), Block::Set(1));
+        assert_eq!(*mirrored.get_block((3, 2).into()), Block::Set(2));
+        assert_eq!(*mirrored.get_block((0, 0).into()), Block::Set(3));
+        assert_eq!(*mirrored.get_block((0, 2).into()), Block::Clear);
+        assert_eq!(mirrored.mirrored().blocks(), pf.blocks());
+    }
+
+    fn pf_from_rows(rows: &[&str]) -> Playfield {
+        let mut pf = Playfield::new("pf", rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if let Some(id) = c.to_digit(10) {
+                    pf.set_block((x as i32, y as i32).into(), Block::Set(id as u8));
+                }
+            }
+        }
+        pf
+    }
+
+    #[test]
+    fn clear_lines_naive() {
+        let rows = ["1...", "22..", "3333", "4.44", "5555"];
+        let mut pf = pf_from_rows(&rows);
+        assert_eq!(pf.clear_lines(Gravity::Naive), vec![vec![2, 4]]);
+        assert_eq!(
+            pf.blocks(),
+            pf_from_rows(&["....", "....", "1...", "22..", "4.44"]).blocks()
+        );
+    }
+
+    #[test]
+    fn clear_lines_sticky() {
+        // The 1 block isn't stuck to the 2 blocks and falls into the gap,
+        // completing a second line
+        let rows = ["2...", "21..", "3333", "3.33"];
+        let mut pf = pf_from_rows(&rows);
+        assert_eq!(pf.clear_lines(Gravity::Sticky), vec![vec![2], vec![3]]);
+        assert_eq!(
+            pf.blocks(),
+            pf_from_rows(&["....", "....", "2...", "2..."]).blocks()
+        );
+    }
+
+    #[test]
+    fn clear_lines_cascade() {
+        // Same as above, but all connected blocks fall as one group that
+        // can't reach the gap
+        let rows = ["2...", "21..", "3333", "3.33"];
+        let mut pf = pf_from_rows(&rows);
+        assert_eq!(pf.clear_lines(Gravity::Cascade), vec![vec![2]]);
+        assert_eq!(
+            pf.blocks(),
+            pf_from_rows(&["....", "2...", "21..", "3.33"]).blocks()
+        );
+
+        // Floating block falls into the gap
+        let rows = [".1..", "2222", "3.33"];
+        let mut pf = pf_from_rows(&rows);
+        assert_eq!(pf.clear_lines(Gravity::Cascade), vec![vec![1], vec![2]]);
+        assert_eq!(pf.blocks(), Playfield::new("pf", 4, 3).blocks());
+    }
 }
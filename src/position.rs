@@ -1,3 +1,4 @@
+use crate::figure::Figure;
 use crate::movement::Movement;
 use crate::vec2::Vec2;
 use crate::vec3::Vec3;
@@ -37,4 +38,15 @@ impl Position {
         }
         self.z %= num_directions;
     }
+
+    //
+    // Map a position of figure on a playfield of width pf_width to the
+    // equivalent position of the mirrored figure on the mirrored playfield
+    //
+    pub fn mirrored(&self, fig: &Figure, pf_width: u32) -> Self {
+        let box_width = i32::from(fig.face_box_width(self.z));
+        let mut pos = Position::new((pf_width as i32 - box_width - self.x, self.y, -self.z));
+        pos.normalize_dir(fig.num_faces());
+        pos
+    }
 }
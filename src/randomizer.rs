@@ -0,0 +1,125 @@
+use rand::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RandomizerKind {
+    // Every figure is equally likely every time
+    Uniform,
+    // Every figure is dealt once in a random order before the next bag
+    Bag,
+}
+
+//
+// Deals figures as indexes into a list of figures. The state of a bag
+// randomizer is the figures left in the current bag, so a randomizer kept
+// in sync with a game (by observing the figures the game deals) can be
+// used to sample the figures that aren't visible yet.
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Randomizer {
+    kind: RandomizerKind,
+    num_figures: usize,
+    // Figures left in the current bag
+    bag: Vec<usize>,
+}
+
+impl Randomizer {
+    pub fn new(kind: RandomizerKind, num_figures: usize) -> Self {
+        Randomizer {
+            kind,
+            num_figures,
+            bag: (0..num_figures).collect(),
+        }
+    }
+
+    pub fn kind(&self) -> RandomizerKind {
+        self.kind
+    }
+
+    pub fn num_figures(&self) -> usize {
+        self.num_figures
+    }
+
+    //
+    // Figures that can be dealt next
+    //
+    pub fn remaining(&self) -> &[usize] {
+        &self.bag
+    }
+
+    pub fn next<R: Rng>(&mut self, rng: &mut R) -> usize {
+        let index = rng.gen_range(0, self.bag.len());
+        let figure = self.bag[index];
+        if self.kind == RandomizerKind::Bag {
+            self.take(index);
+        }
+        figure
+    }
+
+    //
+    // Update the state with a figure dealt by someone else. A figure
+    // that isn't left in the bag starts a new bag.
+    //
+    pub fn observe(&mut self, figure: usize) {
+        if self.kind == RandomizerKind::Uniform {
+            return;
+        }
+        if !self.bag.contains(&figure) {
+            self.bag = (0..self.num_figures).collect();
+        }
+        if let Some(index) = self.bag.iter().position(|f| *f == figure) {
+            self.take(index);
+        }
+    }
+
+    fn take(&mut self, index: usize) {
+        self.bag.swap_remove(index);
+        if self.bag.is_empty() {
+            self.bag = (0..self.num_figures).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn bag() {
+        let mut rng = SmallRng::seed_from_u64(45);
+        let mut randomizer = Randomizer::new(RandomizerKind::Bag, 7);
+        for _ in 0..10 {
+            let mut bag: Vec<_> = (0..7).map(|_| randomizer.next(&mut rng)).collect();
+            bag.sort();
+            assert_eq!(bag, (0..7).collect::<Vec<_>>());
+        }
+
+        // The last figure of a bag is known
+        for figure in &[3, 0, 6, 1, 5, 2] {
+            randomizer.observe(*figure);
+        }
+        assert_eq!(randomizer.remaining(), &[4]);
+        assert_eq!(randomizer.next(&mut rng), 4);
+        assert_eq!(randomizer.remaining().len(), 7);
+
+        // Out of sync, starts a new bag
+        randomizer.observe(2);
+        randomizer.observe(2);
+        assert_eq!(randomizer.remaining().len(), 6);
+        assert!(!randomizer.remaining().contains(&2));
+    }
+
+    #[test]
+    fn uniform() {
+        let mut rng = SmallRng::seed_from_u64(45);
+        let mut randomizer = Randomizer::new(RandomizerKind::Uniform, 7);
+        let mut counts = [0; 7];
+        for _ in 0..700 {
+            counts[randomizer.next(&mut rng)] += 1;
+        }
+        randomizer.observe(3);
+        assert_eq!(randomizer.remaining().len(), 7);
+        assert!(counts.iter().all(|count| *count > 50));
+    }
+}
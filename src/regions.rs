@@ -0,0 +1,351 @@
+use crate::board::Board;
+use crate::vec2::Vec2;
+
+const NO_REGION: u32 = u32::MAX;
+
+//
+// Connected regions of free cells of a board. Cells are connected to the
+// free cells left, right, above and below them.
+//
+// A region is open if it reaches the top row of the analyzed rows, figures
+// can only get to cells of open regions. The free cells of closed regions
+// are holes that can't be filled before lines above them are cleared.
+//
+// A region is linked when a column has a cell of it and a cell of another
+// region with only occupied cells between them. Clearing those rows joins
+// the regions, unlinked regions keep their cells to themselves.
+//
+// Parities are counted over all free cells, as the free cells of one color
+// minus those of the other: cells with x + y even minus odd for the
+// checkerboard and cells in even minus odd columns for the columns.
+//
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Regions {
+    width: u32,
+    height: u32,
+    top: i32,
+    // Region of each cell, NO_REGION for occupied cells and cells above top
+    labels: Vec<u32>,
+    sizes: Vec<u32>,
+    open: Vec<bool>,
+    linked: Vec<bool>,
+    free_rows: u32,
+    checkerboard_parity: i32,
+    column_parity: i32,
+
+    // Cache variable
+    stack: Vec<Vec2<i32>>,
+}
+
+impl Regions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_board<B: Board>(board: &B) -> Self {
+        let mut regions = Self::new();
+        regions.compute(board);
+        regions
+    }
+
+    pub fn compute<B: Board>(&mut self, board: &B) {
+        self.compute_rows(board, 0);
+    }
+
+    //
+    // Only analyze the rows from top down to the bottom of the board, as if
+    // everything above was out of the board
+    //
+    pub fn compute_rows<B: Board>(&mut self, board: &B, top: i32) {
+        self.width = board.width();
+        self.height = board.height();
+        self.top = top.max(0);
+        self.labels.clear();
+        self.labels
+            .resize((self.width * self.height) as usize, NO_REGION);
+        self.sizes.clear();
+        self.open.clear();
+        self.linked.clear();
+        self.free_rows = 0;
+        self.checkerboard_parity = 0;
+        self.column_parity = 0;
+
+        for y in self.top..self.height as i32 {
+            let mut free_row = false;
+            for x in 0..self.width as i32 {
+                let point = Vec2::from((x, y));
+                if board.occupied(point) {
+                    continue;
+                }
+                free_row = true;
+                self.checkerboard_parity += if (x + y) % 2 == 0 { 1 } else { -1 };
+                self.column_parity += if x % 2 == 0 { 1 } else { -1 };
+                if self.labels[self.index(point)] == NO_REGION {
+                    self.fill(board, point);
+                }
+            }
+            if free_row {
+                self.free_rows += 1;
+            }
+        }
+        self.link();
+    }
+
+    // Find the regions facing another region across occupied cells
+    fn link(&mut self) {
+        self.linked.resize(self.sizes.len(), false);
+        for x in 0..self.width as i32 {
+            // Region of the last free cell and if occupied cells followed
+            let mut above = None;
+            let mut covered = false;
+            for y in self.top..self.height as i32 {
+                let label = self.labels[self.index((x, y).into())];
+                if label == NO_REGION {
+                    covered = above.is_some();
+                    continue;
+                }
+                match above {
+                    Some(region) if covered && region != label => {
+                        self.linked[region as usize] = true;
+                        self.linked[label as usize] = true;
+                    }
+                    _ => {}
+                }
+                above = Some(label);
+                covered = false;
+            }
+        }
+    }
+
+    // Label the region of a free cell
+    fn fill<B: Board>(&mut self, board: &B, start: Vec2<i32>) {
+        let region = self.sizes.len() as u32;
+        let mut size = 0;
+        let mut open = false;
+        let index = self.index(start);
+        self.labels[index] = region;
+        self.stack.push(start);
+        while let Some(point) = self.stack.pop() {
+            size += 1;
+            open |= point.y == self.top;
+            for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                let neighbour = Vec2::from((point.x + dx, point.y + dy));
+                if neighbour.y < self.top || board.occupied(neighbour) {
+                    continue;
+                }
+                let index = self.index(neighbour);
+                if self.labels[index] == NO_REGION {
+                    self.labels[index] = region;
+                    self.stack.push(neighbour);
+                }
+            }
+        }
+        self.sizes.push(size);
+        self.open.push(open);
+    }
+
+    fn index(&self, point: Vec2<i32>) -> usize {
+        (point.y as u32 * self.width + point.x as u32) as usize
+    }
+
+    //
+    // Region of a cell, None for occupied cells and cells outside of the
+    // analyzed rows
+    //
+    pub fn region(&self, point: Vec2<i32>) -> Option<usize> {
+        if point.x < 0
+            || point.x >= self.width as i32
+            || point.y < self.top
+            || point.y >= self.height as i32
+        {
+            return None;
+        }
+        match self.labels[self.index(point)] {
+            NO_REGION => None,
+            region => Some(region as usize),
+        }
+    }
+
+    pub fn num_regions(&self) -> usize {
+        self.sizes.len()
+    }
+
+    // Number of free cells of each region
+    pub fn sizes(&self) -> &[u32] {
+        &self.sizes
+    }
+
+    pub fn is_open(&self, region: usize) -> bool {
+        self.open[region]
+    }
+
+    pub fn is_linked(&self, region: usize) -> bool {
+        self.linked[region]
+    }
+
+    pub fn free_cells(&self) -> u32 {
+        self.sizes.iter().sum()
+    }
+
+    // Number of analyzed rows with free cells
+    pub fn free_rows(&self) -> u32 {
+        self.free_rows
+    }
+
+    //
+    // Test if a free cell can be reached from the top
+    //
+    pub fn is_reachable(&self, point: Vec2<i32>) -> bool {
+        self.region(point).is_some_and(|region| self.open[region])
+    }
+
+    //
+    // Free cells that can't be reached from the top
+    //
+    pub fn unreachable_cells(&self) -> u32 {
+        self.sizes
+            .iter()
+            .zip(&self.open)
+            .filter(|(_, open)| !**open)
+            .map(|(size, _)| size)
+            .sum()
+    }
+
+    //
+    // Size of each region modulo the number of blocks of a figure, a region
+    // with a remainder can't be filled exactly by such figures
+    //
+    pub fn size_remainders(&self, remainders: &mut Vec<u32>, cells: u32) {
+        remainders.clear();
+        remainders.extend(self.sizes.iter().map(|size| size % cells));
+    }
+
+    pub fn checkerboard_parity(&self) -> i32 {
+        self.checkerboard_parity
+    }
+
+    pub fn column_parity(&self) -> i32 {
+        self.column_parity
+    }
+
+    //
+    // Test if the analyzed rows can't be emptied by figures with the given
+    // number of blocks without clearing a line first: a free cell can't be
+    // reached from the top or a region can't be filled exactly. Clearing a
+    // line can open a covered cell or join linked regions, so a dead board
+    // may still be emptied that way.
+    //
+    pub fn is_dead(&self, cells: u32) -> bool {
+        self.unreachable_cells() > 0 || self.sizes.iter().any(|size| size % cells != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::block::Block;
+    use crate::playfield::Playfield;
+
+    // Board from rows of '#' (occupied) and '.' (free), top row first
+    fn board(rows: &[&str]) -> Playfield {
+        let mut pf = Playfield::new("pf", rows[0].len() as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                if c == '#' {
+                    pf.set_block((x as i32, y as i32).into(), Block::Set(1));
+                }
+            }
+        }
+        pf
+    }
+
+    #[test]
+    fn empty_board() {
+        let regions = Regions::from_board(&Playfield::new("pf", 4, 3));
+        assert_eq!(regions.num_regions(), 1);
+        assert_eq!(regions.sizes(), &[12]);
+        assert!(regions.is_open(0));
+        assert_eq!(regions.unreachable_cells(), 0);
+        assert_eq!(regions.checkerboard_parity(), 0);
+        assert_eq!(regions.column_parity(), 0);
+        assert!(!regions.is_dead(4));
+        assert!(regions.is_dead(5));
+    }
+
+    #[test]
+    fn regions() {
+        let pf = board(&[
+            "....", //
+            ".###", //
+            ".#..", //
+            "##.#", //
+        ]);
+        let mut regions = Regions::from_board(&pf);
+        assert_eq!(regions.num_regions(), 2);
+        assert_eq!(regions.sizes(), &[6, 3]);
+        assert_eq!(regions.region((0, 2).into()), Some(0));
+        assert_eq!(regions.region((2, 3).into()), Some(1));
+        assert_eq!(regions.region((1, 1).into()), None);
+        assert_eq!(regions.region((4, 0).into()), None);
+        assert!(regions.is_linked(0));
+        assert!(regions.is_linked(1));
+        assert!(regions.is_reachable((0, 2).into()));
+        assert!(!regions.is_reachable((3, 2).into()));
+        assert!(!regions.is_reachable((1, 2).into()));
+        assert_eq!(regions.unreachable_cells(), 3);
+        assert_eq!(regions.free_cells(), 9);
+        assert_eq!(regions.free_rows(), 4);
+        // Free cells at (0,0) (2,0) (0,2) (2,2) even, (1,0) (3,0) (0,1)
+        // (3,2) (2,3) odd
+        assert_eq!(regions.checkerboard_parity(), -1);
+        // Columns 0 and 2: 3 + 3, columns 1 and 3: 1 + 2
+        assert_eq!(regions.column_parity(), 3);
+
+        let mut remainders = Vec::new();
+        regions.size_remainders(&mut remainders, 4);
+        assert_eq!(remainders, vec![2, 3]);
+        assert!(regions.is_dead(3));
+
+        // Same result for another board type
+        regions.compute(&BitBoard::from_playfield(&pf));
+        assert_eq!(regions.sizes(), &[6, 3]);
+
+        // The enclosed cells are open below the covering row
+        regions.compute_rows(&pf, 2);
+        assert_eq!(regions.sizes(), &[1, 3]);
+        assert_eq!(regions.free_rows(), 2);
+        assert_eq!(regions.unreachable_cells(), 0);
+        assert_eq!(regions.region((0, 0).into()), None);
+        assert!(regions.is_dead(2));
+        assert!(!regions.is_dead(1));
+    }
+
+    #[test]
+    fn dead_before_clear() {
+        // Covered cell below a line missing its last cell. Filling the top
+        // row and the right column clears line 1 and opens the cell.
+        let pf = board(&[
+            "....", //
+            "###.", //
+            ".###", //
+        ]);
+        let regions = Regions::from_board(&pf);
+        assert_eq!(regions.sizes(), &[5, 1]);
+        assert_eq!(regions.unreachable_cells(), 1);
+        assert!(regions.is_linked(0));
+        assert!(regions.is_linked(1));
+        assert!(regions.is_dead(4));
+
+        // Regions side by side stay apart
+        let pf = board(&[
+            "..#.", //
+            "#.##", //
+            "####", //
+        ]);
+        let regions = Regions::from_board(&pf);
+        assert_eq!(regions.sizes(), &[3, 1]);
+        assert!(!regions.is_linked(0));
+        assert!(!regions.is_linked(1));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::board::Board;
+use crate::figure::Figure;
+use crate::vec2::Vec2;
+
+//
+// Zobrist hashing of boards. Each cell has a fixed random key and the hash
+// of a board is the xor of the keys of its occupied cells, so setting or
+// clearing a block only has to xor in the key of its cell. Only occupancy
+// is hashed, boards with the same blocks in different colors hash the
+// same.
+//
+// Keys are derived from the cell coordinates and don't depend on the size
+// of the board, the same blocks give the same hash on every board type.
+//
+
+// SplitMix64 finalizer, spreads the bits of a counter over the whole key
+fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+pub fn cell_key(point: Vec2<i32>) -> u64 {
+    mix((u64::from(point.y as u32) << 32) | u64::from(point.x as u32))
+}
+
+//
+// Key for other parts of a search state than the board, e.g. the figure
+// in hold, kept apart from the cell keys
+//
+pub fn state_key(kind: u32, value: u64) -> u64 {
+    mix(mix(u64::from(kind) | (1 << 63)) ^ value)
+}
+
+//
+// Value for state_key identifying a figure, figures with the same name get
+// the same value wherever they are in the queue
+//
+pub fn figure_value(fig: &Figure) -> u64 {
+    fig.name()
+        .bytes()
+        .fold(0, |value, byte| mix(value ^ u64::from(byte)))
+}
+
+//
+// Hash of a board computed from all of its blocks
+//
+pub fn hash_board<B: Board>(board: &B) -> u64 {
+    let mut hash = 0;
+    for y in 0..board.height() as i32 {
+        for x in 0..board.width() as i32 {
+            if board.occupied((x, y).into()) {
+                hash ^= cell_key((x, y).into());
+            }
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::block::Block;
+    use crate::figure::{standard_figures, Figure};
+    use crate::playfield::{Gravity, Playfield};
+
+    fn fig_t() -> Figure {
+        standard_figures()[0].clone()
+    }
+
+    fn fig_o() -> Figure {
+        standard_figures()[3].clone()
+    }
+
+    #[test]
+    fn keys() {
+        assert_ne!(cell_key((0, 1).into()), cell_key((1, 0).into()));
+        assert_ne!(cell_key((0, 0).into()), 0);
+        assert_ne!(state_key(0, 0), state_key(1, 0));
+        assert_ne!(state_key(0, 0), state_key(0, 1));
+        assert_eq!(figure_value(&fig_t()), figure_value(&standard_figures()[0]));
+        assert_ne!(figure_value(&fig_t()), figure_value(&fig_o()));
+        assert_eq!(hash_board(&Playfield::new("pf", 10, 20)), 0);
+    }
+
+    #[test]
+    fn incremental() {
+        let mut pf = Playfield::new("pf", 6, 6);
+        pf.set_block((0, 5).into(), Block::Set(1));
+        assert_eq!(pf.zobrist_hash(), cell_key((0, 5).into()));
+        // Setting a set block again or in another color changes nothing
+        pf.set_block((0, 5).into(), Block::Set(2));
+        assert_eq!(pf.zobrist_hash(), cell_key((0, 5).into()));
+        pf.clear_block((0, 5).into());
+        assert_eq!(pf.zobrist_hash(), 0);
+        // Blocks outside are ignored
+        pf.set_block((6, 0).into(), Block::Set(1));
+        assert_eq!(pf.zobrist_hash(), 0);
+
+        // Placing in either order and removing again
+        let mut other = pf.clone();
+        fig_t().place(&mut pf, (0, 3, 0).into());
+        fig_o().place(&mut pf, (3, 4, 0).into());
+        fig_o().place(&mut other, (3, 4, 0).into());
+        fig_t().place(&mut other, (0, 3, 0).into());
+        assert_eq!(pf.zobrist_hash(), other.zobrist_hash());
+        assert_eq!(pf.zobrist_hash(), hash_board(&pf));
+        assert_eq!(
+            pf.zobrist_hash(),
+            hash_board(&BitBoard::from_playfield(&pf))
+        );
+        fig_t().remove(&mut other, (0, 3, 0).into());
+        fig_o().remove(&mut other, (3, 4, 0).into());
+        assert_eq!(other.zobrist_hash(), 0);
+
+        let mut copy = Playfield::new("copy", 6, 6);
+        copy.copy(&pf);
+        assert_eq!(copy.zobrist_hash(), pf.zobrist_hash());
+        assert_eq!(pf.mirrored().zobrist_hash(), hash_board(&pf.mirrored()));
+    }
+
+    #[test]
+    fn line_clears() {
+        let rows = ["1...", "22..", "3333", "4.44", "5555"];
+        let after = ["....", "....", "1...", "22..", "4.44"];
+        let build = |rows: &[&str]| {
+            let mut pf = Playfield::new("pf", 4, rows.len() as u32);
+            for (y, row) in rows.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    if let Some(id) = c.to_digit(10) {
+                        pf.set_block((x as i32, y as i32).into(), Block::Set(id as u8));
+                    }
+                }
+            }
+            pf
+        };
+        let mut pf = build(&rows);
+        pf.clear_lines(Gravity::Naive);
+        assert_eq!(pf.zobrist_hash(), build(&after).zobrist_hash());
+
+        let rows = ["2...", "21..", "3333", "3.33"];
+        for gravity in [Gravity::Sticky, Gravity::Cascade] {
+            let mut pf = build(&rows);
+            pf.clear_lines(gravity);
+            assert_eq!(pf.zobrist_hash(), hash_board(&pf));
+        }
+    }
+
+    #[test]
+    fn bitboard() {
+        let mut bb = BitBoard::new(4, 5);
+        bb.set_block((0, 4).into());
+        assert_eq!(bb.zobrist_hash(), cell_key((0, 4).into()));
+        bb.set_block((0, 4).into());
+        assert_eq!(bb.zobrist_hash(), cell_key((0, 4).into()));
+        bb.clear_block((0, 4).into());
+        assert_eq!(bb.zobrist_hash(), 0);
+
+        // Placing, removing and clearing lines as a playfield does
+        let mut pf = Playfield::new("pf", 4, 5);
+        fig_t().place(&mut pf, (0, 2, 0).into());
+        fig_o().place(&mut pf, (2, 3, 0).into());
+        fig_t().place(&mut bb, (0, 2, 0).into());
+        fig_o().place(&mut bb, (2, 3, 0).into());
+        assert_eq!(bb.zobrist_hash(), pf.zobrist_hash());
+        fig_o().remove(&mut bb, (2, 3, 0).into());
+        fig_o().place(&mut bb, (2, 3, 0).into());
+        for line in pf.locked_lines() {
+            pf.throw_line(line);
+            bb.throw_line(line);
+        }
+        assert_eq!(bb.zobrist_hash(), pf.zobrist_hash());
+        assert_eq!(bb.zobrist_hash(), hash_board(&bb));
+
+        let mut copy = BitBoard::new(4, 5);
+        copy.copy_from(&bb);
+        assert_eq!(copy.zobrist_hash(), bb.zobrist_hash());
+        assert_eq!(
+            BitBoard::from_playfield(&pf).zobrist_hash(),
+            bb.zobrist_hash()
+        );
+    }
+}